@@ -1,5 +1,6 @@
 import React, { useState } from 'react';
 import { usePerpetualTrading } from '@/hooks/use-perpetual-trading';
+import { NO_MAX_PRICE, NO_MIN_PRICE } from '@/lib/perpetual-trading-client';
 import { useCoinGeckoPrices } from '@/hooks/use-coingecko-prices';
 import { useWalletContext } from '@/contexts/WalletContext';
 import { Button } from '@/components/ui/button';
@@ -74,12 +75,15 @@ export const ContractTester: React.FC = () => {
         return;
       }
 
+      // El tester no tiene control de slippage, así que acepta cualquier precio
       const isLong = positionForm.side === 'long';
       const positionId = await openPosition(
         walletInfo.publicKey,
+        positionForm.asset,
         margin,
         leverage,
-        isLong
+        isLong,
+        isLong ? NO_MAX_PRICE : NO_MIN_PRICE
       );
       
       alert(`Posición abierta exitosamente! ID: ${positionId}`);
@@ -131,7 +135,10 @@ export const ContractTester: React.FC = () => {
         return;
       }
 
-      const pnl = await closePosition(walletInfo.publicKey, positionId);
+      // Cerrar un long vende y cerrar un short compra; sin límite de slippage
+      const position = await getCurrentPosition(positionId);
+      const acceptablePrice = position?.isLong ? NO_MIN_PRICE : NO_MAX_PRICE;
+      const pnl = await closePosition(walletInfo.publicKey, positionId, acceptablePrice);
       alert(`Posición cerrada! PnL: ${pnl / 10_000_000} XLM`);
     } catch (error) {
       console.error('Error cerrando posición:', error);
@@ -150,10 +157,12 @@ export const ContractTester: React.FC = () => {
     }
   };
 
-  // Obtener posición actual
+  // Obtener la última posición del usuario
   const handleGetCurrentPosition = async () => {
     try {
-      const position = await getCurrentPosition();
+      const positionIds = userAddress ? await getTraderPositions(userAddress) : [];
+      const positionId = positionIds[positionIds.length - 1];
+      const position = positionId !== undefined ? await getCurrentPosition(positionId) : null;
       if (position) {
         alert(`Posición actual: ${JSON.stringify(position, null, 2)}`);
       } else {
@@ -73,7 +73,7 @@ fi
 echo "✅ Price Oracle Contract deployed successfully!"
 echo "📄 Contract ID: $CONTRACT_ID"
 
-# Initialize the contract with Reflector Oracle address
+# Initialize the contract with admin and Reflector Oracle address
 echo ""
 echo "🔧 Initializing contract with Reflector Oracle..."
 INIT_RESULT=$(stellar contract invoke \
@@ -83,6 +83,7 @@ INIT_RESULT=$(stellar contract invoke \
     --rpc-url "$STELLAR_RPC_URL" \
     --network-passphrase "$STELLAR_NETWORK_PASSPHRASE" \
     -- initialize \
+    --admin "$ADMIN_ADDRESS" \
     --reflector_address "CALI2BYU2JE6WVRUFYTS6MSBNEHGJ35P4AVCZYF3B6QOE3QKOB2PLE6M")
 
 echo "Initialize result: $INIT_RESULT"
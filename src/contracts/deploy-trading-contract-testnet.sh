@@ -52,7 +52,7 @@ fi
 echo "✅ Perpetual Trading Contract deployed successfully!"
 echo "📄 Contract ID: $CONTRACT_ID"
 
-# Initialize the contract with admin and price oracle
+# Initialize the contract with admin, price oracle and collateral token (native XLM)
 echo ""
 echo "🔧 Initializing contract with admin and price oracle..."
 INIT_RESULT=$(stellar contract invoke \
@@ -63,7 +63,8 @@ INIT_RESULT=$(stellar contract invoke \
     --network-passphrase "Test SDF Network ; September 2015" \
     -- initialize \
     --admin "GB4UZ6VHOD6ZDCBHJPI3OSFHBXTFTD75YNVLO3YT7PLC3RZANKFROEMZ" \
-    --price_oracle "CAYMTS6FAAYPCYUSMGRIIHSRBLTWB53EYPMANEV6UZMHE4VIBINF52TD")
+    --price_oracle "CAYMTS6FAAYPCYUSMGRIIHSRBLTWB53EYPMANEV6UZMHE4VIBINF52TD" \
+    --collateral_token "CDLZFC3SYJYDZT7K67VZ75HPJVIEUVNIXF47ZG2FB2RMQQVU2HHGCYSC")
 
 echo "Initialize result: $INIT_RESULT"
 
@@ -39,11 +39,15 @@ if [ -z "$PRICE_ORACLE_CONTRACT_ID" ]; then
     exit 1
 fi
 
+# Collateral defaults to the native XLM Stellar Asset Contract
+COLLATERAL_TOKEN_ID="${COLLATERAL_TOKEN_ID:-CAS3J7GYLGXMF6TDJBBYYSE3HQ6BBSMLNUQ34T6TZMYMW2EVH34XOWMA}"
+
 echo "📋 Configuration:"
 echo "  Network: $STELLAR_NETWORK"
 echo "  RPC URL: $STELLAR_RPC_URL"
 echo "  Admin: $ADMIN_ADDRESS"
 echo "  Price Oracle: $PRICE_ORACLE_CONTRACT_ID"
+echo "  Collateral Token: $COLLATERAL_TOKEN_ID"
 echo ""
 
 # Confirm deployment
@@ -81,7 +85,7 @@ fi
 echo "✅ Perpetual Trading Contract deployed successfully!"
 echo "📄 Contract ID: $CONTRACT_ID"
 
-# Initialize the contract with admin and price oracle
+# Initialize the contract with admin, price oracle and collateral token
 echo ""
 echo "🔧 Initializing contract with admin and price oracle..."
 INIT_RESULT=$(stellar contract invoke \
@@ -92,7 +96,8 @@ INIT_RESULT=$(stellar contract invoke \
     --network-passphrase "$STELLAR_NETWORK_PASSPHRASE" \
     -- initialize \
     --admin "$ADMIN_ADDRESS" \
-    --price_oracle "$PRICE_ORACLE_CONTRACT_ID")
+    --price_oracle "$PRICE_ORACLE_CONTRACT_ID" \
+    --collateral_token "$COLLATERAL_TOKEN_ID")
 
 echo "Initialize result: $INIT_RESULT"
 
@@ -108,6 +113,7 @@ echo "  Contract ID: $CONTRACT_ID"
 echo "  Network: Mainnet"
 echo "  Admin: $ADMIN_ADDRESS"
 echo "  Price Oracle: $PRICE_ORACLE_CONTRACT_ID"
+echo "  Collateral Token: $COLLATERAL_TOKEN_ID"
 echo ""
 echo "🎉 Your Perpetual Trading contract is now live on Stellar Mainnet!"
 echo ""
@@ -0,0 +1,41 @@
+use soroban_sdk::contracterror;
+
+// Possible runtime errors, with stable codes for clients and keepers
+#[contracterror]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[repr(u32)]
+pub enum Error {
+    NotInitialized = 1,
+    Unauthorized = 2,
+    InvalidLeverage = 3,
+    MarginTooLow = 4,
+    InvalidAmount = 5,
+    PositionNotFound = 6,
+    PositionClosed = 7,
+    MarketNotFound = 8,
+    MarketDisabled = 9,
+    InvalidMarketConfig = 10,
+    PriceUnavailable = 11,
+    PriceStale = 12,
+    NotLiquidatable = 13,
+    InsufficientBalance = 14,
+    ContractPaused = 15,
+    CloseOnly = 16,
+    MarketPaused = 17,
+    AlreadyInitialized = 18,
+    InvalidConfig = 19,
+    NoPendingAdmin = 20,
+    InvalidVersion = 21,
+    InsufficientLiquidity = 22,
+    CoverageNotFound = 23,
+    OrderNotFound = 24,
+    OrderNotOpen = 25,
+    OrderExpired = 26,
+    OrderNotTriggered = 27,
+    InvalidTrigger = 28,
+    TriggerNotMet = 29,
+    SlippageExceeded = 30,
+    OrderNotExpired = 31,
+    OpenInterestCapExceeded = 32,
+    TraderNotionalCapExceeded = 33,
+}
@@ -0,0 +1,184 @@
+// Contract events; topics are (event type, market, trader) where they apply,
+// and every payload starts with EVENT_VERSION so consumers can decode safely
+
+use soroban_sdk::{symbol_short, Address, BytesN, Env, Symbol};
+
+use crate::{Config, InsuranceCoverage, Market, MarketState, Order, PauseMode, Position, Role};
+
+pub const EVENT_VERSION: u32 = 1;
+
+pub fn position_opened(e: &Env, position: &Position) {
+    e.events().publish(
+        (symbol_short!("open"), position.market.clone(), position.trader.clone()),
+        (EVENT_VERSION, position.id, position.is_long, position.size, position.margin, position.entry_price),
+    );
+}
+
+pub fn position_closed(e: &Env, position: &Position, payout: i128) {
+    e.events().publish(
+        (symbol_short!("close"), position.market.clone(), position.trader.clone()),
+        (EVENT_VERSION, position.id, position.close_price, position.pnl, payout),
+    );
+}
+
+pub fn margin_changed(e: &Env, position: &Position, amount: i128) {
+    e.events().publish(
+        (symbol_short!("margin"), position.market.clone(), position.trader.clone()),
+        (EVENT_VERSION, position.id, amount, position.margin),
+    );
+}
+
+pub fn position_increased(e: &Env, position: &Position, size_delta: i128) {
+    e.events().publish(
+        (symbol_short!("increase"), position.market.clone(), position.trader.clone()),
+        (EVENT_VERSION, position.id, size_delta, position.size, position.margin, position.entry_price),
+    );
+}
+
+pub fn position_reduced(e: &Env, position: &Position, size_delta: i128, pnl: i128, payout: i128) {
+    e.events().publish(
+        (symbol_short!("reduce"), position.market.clone(), position.trader.clone()),
+        (EVENT_VERSION, position.id, size_delta, pnl, payout, position.size),
+    );
+}
+
+pub fn triggers_updated(e: &Env, position: &Position) {
+    e.events().publish(
+        (symbol_short!("triggers"), position.market.clone(), position.trader.clone()),
+        (EVENT_VERSION, position.id, position.stop_loss, position.take_profit, position.trigger_size),
+    );
+}
+
+pub fn position_triggered(e: &Env, position: &Position, price: i128, stop_loss: bool) {
+    e.events().publish(
+        (symbol_short!("trigger"), position.market.clone(), position.trader.clone()),
+        (EVENT_VERSION, position.id, price, stop_loss),
+    );
+}
+
+pub fn position_liquidated(e: &Env, position: &Position, keeper: &Address, keeper_reward: i128) {
+    e.events().publish(
+        (symbol_short!("liquidate"), position.market.clone(), position.trader.clone()),
+        (EVENT_VERSION, position.id, keeper.clone(), position.close_price, position.pnl, keeper_reward),
+    );
+}
+
+pub fn order_placed(e: &Env, order: &Order) {
+    e.events().publish(
+        (symbol_short!("order"), order.market.clone(), order.trader.clone()),
+        (EVENT_VERSION, order.id, order.kind, order.is_long, order.margin, order.leverage, order.limit_price, order.expiry),
+    );
+}
+
+pub fn order_cancelled(e: &Env, order: &Order) {
+    e.events().publish(
+        (symbol_short!("ord_cncl"), order.market.clone(), order.trader.clone()),
+        (EVENT_VERSION, order.id),
+    );
+}
+
+pub fn order_executed(e: &Env, order: &Order, keeper: &Address) {
+    e.events().publish(
+        (symbol_short!("ord_exec"), order.market.clone(), order.trader.clone()),
+        (EVENT_VERSION, order.id, order.position_id, keeper.clone(), order.keeper_fee),
+    );
+}
+
+pub fn funding_settled(e: &Env, market: &Symbol, state: &MarketState) {
+    e.events().publish(
+        (symbol_short!("funding"), market.clone()),
+        (EVENT_VERSION, state.funding_index, state.long_open_interest, state.short_open_interest),
+    );
+}
+
+pub fn market_updated(e: &Env, admin: &Address, market: &Market) {
+    e.events().publish(
+        (symbol_short!("market"), market.id.clone(), admin.clone()),
+        (EVENT_VERSION, market.clone()),
+    );
+}
+
+pub fn config_updated(e: &Env, admin: &Address, config: &Config) {
+    e.events().publish((symbol_short!("config"), admin.clone()), (EVENT_VERSION, config.clone()));
+}
+
+pub fn contract_upgraded(e: &Env, admin: &Address, new_wasm_hash: &BytesN<32>) {
+    e.events().publish((symbol_short!("upgrade"), admin.clone()), (EVENT_VERSION, new_wasm_hash.clone()));
+}
+
+pub fn contract_migrated(e: &Env, admin: &Address, from_version: u32, to_version: u32) {
+    e.events().publish((symbol_short!("migrate"), admin.clone()), (EVENT_VERSION, from_version, to_version));
+}
+
+pub fn admin_proposed(e: &Env, admin: &Address, new_admin: &Address) {
+    e.events().publish(
+        (symbol_short!("adm_prop"), admin.clone()),
+        (EVENT_VERSION, new_admin.clone()),
+    );
+}
+
+pub fn admin_accepted(e: &Env, previous_admin: &Address, new_admin: &Address) {
+    e.events().publish(
+        (symbol_short!("adm_accpt"), new_admin.clone()),
+        (EVENT_VERSION, previous_admin.clone()),
+    );
+}
+
+pub fn role_granted(e: &Env, admin: &Address, role: Role, account: &Address) {
+    e.events().publish(
+        (symbol_short!("role_add"), role, account.clone()),
+        (EVENT_VERSION, admin.clone()),
+    );
+}
+
+pub fn role_revoked(e: &Env, admin: &Address, role: Role, account: &Address) {
+    e.events().publish(
+        (symbol_short!("role_del"), role, account.clone()),
+        (EVENT_VERSION, admin.clone()),
+    );
+}
+
+pub fn liquidity_deposited(e: &Env, provider: &Address, amount: i128, shares: i128) {
+    e.events().publish((symbol_short!("lp_dep"), provider.clone()), (EVENT_VERSION, amount, shares));
+}
+
+pub fn liquidity_withdrawn(e: &Env, provider: &Address, amount: i128, shares: i128) {
+    e.events().publish((symbol_short!("lp_wdr"), provider.clone()), (EVENT_VERSION, amount, shares));
+}
+
+pub fn insurance_funded(e: &Env, funder: &Address, amount: i128) {
+    e.events().publish((symbol_short!("ins_fund"), funder.clone()), (EVENT_VERSION, amount));
+}
+
+pub fn shortfall_covered(e: &Env, coverage: &InsuranceCoverage) {
+    e.events().publish(
+        (symbol_short!("shortfall"), coverage.position_id),
+        (EVENT_VERSION, coverage.shortfall, coverage.covered),
+    );
+}
+
+pub fn balance_withdrawn(e: &Env, admin: &Address, amount: i128) {
+    e.events().publish(
+        (symbol_short!("withdraw"), admin.clone()),
+        (EVENT_VERSION, amount),
+    );
+}
+
+pub fn contract_paused(e: &Env, admin: &Address) {
+    e.events().publish((symbol_short!("paused"), admin.clone()), (EVENT_VERSION,));
+}
+
+pub fn contract_resumed(e: &Env, admin: &Address) {
+    e.events().publish((symbol_short!("resumed"), admin.clone()), (EVENT_VERSION,));
+}
+
+pub fn pause_mode_changed(e: &Env, admin: &Address, mode: PauseMode) {
+    e.events().publish((symbol_short!("pause_mod"), admin.clone()), (EVENT_VERSION, mode));
+}
+
+pub fn market_paused(e: &Env, admin: &Address, market: &Symbol, paused: bool) {
+    e.events().publish(
+        (symbol_short!("mkt_pause"), market.clone(), admin.clone()),
+        (EVENT_VERSION, paused),
+    );
+}
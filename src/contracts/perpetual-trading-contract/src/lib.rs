@@ -1,6 +1,200 @@
 #![no_std]
+#![allow(clippy::too_many_arguments)]
 
-use soroban_sdk::{contract, contractimpl, Address, Env, Symbol, Map, Vec};
+use soroban_sdk::{contract, contractimpl, contracttype, token, Address, BytesN, Env, Symbol, Vec, I256};
+
+mod error;
+mod events;
+mod oracle;
+mod storage;
+
+#[cfg(test)]
+mod test;
+
+pub use error::Error;
+pub use storage::DataKey;
+
+// Current storage schema version, recorded in Config
+const SCHEMA_VERSION: u32 = 2;
+
+// Default for Config::max_price_age, in seconds
+const DEFAULT_MAX_PRICE_AGE: u64 = 300;
+
+// Basis point denominator used for fees and ratios
+const BPS_DENOMINATOR: i128 = 10_000;
+
+// Fixed-point scale of the cumulative funding index
+const FUNDING_PRECISION: i128 = 1_000_000_000;
+
+// Funding rates are quoted per day
+const SECONDS_PER_DAY: i128 = 86_400;
+
+// Fixed-point scale of position units (size / entry price) used to mark open interest,
+// large enough that units don't round away against 14-decimal oracle prices
+const UNIT_PRECISION: i128 = 1_000_000_000_000_000_000;
+
+// Defaults for Config::lp_fee_share_bps and Config::insurance_fee_share_bps
+const DEFAULT_LP_FEE_SHARE_BPS: u32 = 7_000;
+const DEFAULT_INSURANCE_FEE_SHARE_BPS: u32 = 1_000;
+
+// Default for Config::keeper_fee (0.1 XLM)
+const DEFAULT_KEEPER_FEE: i128 = 1_000_000;
+
+// Contract configuration, stored under DataKey::Config
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Config {
+    pub version: u32, // storage schema version
+    pub admin: Address,
+    pub price_oracle: Address,
+    pub collateral_token: Address,
+    pub max_price_age: u64, // oracle prices older than this (in seconds) are rejected
+    pub lp_fee_share_bps: u32, // share of trading and liquidation fees credited to the LP vault
+    pub insurance_fee_share_bps: u32, // share credited to the insurance fund; the rest goes to the protocol
+    pub keeper_fee: i128, // flat fee escrowed with each order for the keeper that executes it
+}
+
+// A shortfall left by a closed or liquidated position, stored under DataKey::InsuranceCoverage(index)
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InsuranceCoverage {
+    pub position_id: u64,
+    pub shortfall: i128,
+    pub covered: i128, // paid by the insurance fund; the remainder became bad debt
+    pub timestamp: u64,
+}
+
+// A tradable market, stored under DataKey::Market(id)
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Market {
+    pub id: Symbol,
+    pub asset: oracle::Asset,
+    pub max_leverage: u32,
+    pub open_fee_bps: u32,
+    pub close_fee_bps: u32,
+    pub maintenance_margin_bps: u32,
+    pub liquidation_fee_bps: u32,
+    pub keeper_reward_bps: u32, // share of the liquidation fee paid to the keeper
+    pub funding_rate_bps: u32,  // daily funding rate paid by the heavier side at full skew
+    pub max_open_interest: i128,      // cap on long plus short open interest, 0 for none
+    pub max_side_open_interest: i128, // cap on each side's open interest, bounding the skew; 0 for none
+    pub max_trader_notional: i128,    // cap on a single trader's open size in the market, 0 for none
+    pub enabled: bool,
+}
+
+// Current open interest of a market and the size each side can still add under its caps
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OpenInterest {
+    pub long_open_interest: i128,
+    pub short_open_interest: i128,
+    pub long_capacity: i128, // i128::MAX when uncapped
+    pub short_capacity: i128,
+}
+
+// Open interest and funding accumulator of a market, stored under DataKey::MarketState(id)
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MarketState {
+    pub long_open_interest: i128,
+    pub short_open_interest: i128,
+    pub long_units: i128, // sum of size / entry price over open longs, scaled by UNIT_PRECISION
+    pub short_units: i128,
+    pub long_funding_weight: i128, // sum of size * funding index snapshot over open longs
+    pub short_funding_weight: i128,
+    pub funding_index: i128, // cumulative funding per unit of long size, scaled by FUNDING_PRECISION
+    pub last_funding_time: u64,
+}
+
+// Contract-wide trading mode
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PauseMode {
+    Active,    // all trading allowed
+    CloseOnly, // positions can be reduced or liquidated, but no new exposure
+    Halted,    // no trading activity at all
+}
+
+// Delegated permissions; the admin holds all of them implicitly
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Role {
+    Pauser,      // guardian that can halt trading, but not resume it
+    RiskManager, // sets market parameters
+    Treasurer,   // withdraws protocol funds
+}
+
+// Lifecycle of a position
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PositionStatus {
+    Open,
+    Closed,
+    Liquidated,
+}
+
+// A leveraged position, stored under DataKey::Position(id)
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Position {
+    pub id: u64,
+    pub trader: Address,
+    pub market: Symbol,
+    pub margin: i128,
+    pub leverage: u32,
+    pub size: i128,
+    pub is_long: bool,
+    pub entry_price: i128,
+    pub funding_index: i128, // market funding index snapshot at open
+    pub opened_at: u64,
+    pub status: PositionStatus,
+    pub close_price: i128,
+    pub closed_at: u64,
+    pub pnl: i128, // total realized PnL, set once the position is closed or liquidated
+    pub realized_pnl: i128, // running PnL and funding realized by partial closes and increases
+    pub fees_paid: i128, // running open, close and liquidation fees
+    pub stop_loss: Option<i128>,   // close when the price moves against the position to this level
+    pub take_profit: Option<i128>, // close when the price moves in favour of the position to this level
+    pub trigger_size: i128,        // size closed by a trigger; 0 closes the whole position
+}
+
+// Lifecycle of an order
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OrderStatus {
+    Open,
+    Executed,
+    Cancelled,
+}
+
+// How an order is filled
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OrderKind {
+    Limit,  // at the latest price, once it crosses the limit
+    Market, // at the first oracle price published after the request
+}
+
+// A resting order with escrowed margin, stored under DataKey::Order(id)
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Order {
+    pub id: u64,
+    pub kind: OrderKind,
+    pub trader: Address,
+    pub market: Symbol,
+    pub is_long: bool,
+    pub margin: i128,
+    pub leverage: u32,
+    pub limit_price: i128, // limit price, or the acceptable price of a market order
+    pub price_time: u64,   // oracle timestamp a market order fills at, 0 for limit orders
+    pub expiry: u64,
+    pub keeper_fee: i128, // escrowed on top of the margin and paid to the executing keeper
+    pub created_at: u64,
+    pub status: OrderStatus,
+    pub position_id: u64, // position opened by the order, 0 until executed
+}
 
 #[contract]
 pub struct PerpetualTradingContract;
@@ -8,280 +202,1741 @@ pub struct PerpetualTradingContract;
 #[contractimpl]
 impl PerpetualTradingContract {
     // Initialize the contract
-    pub fn initialize(e: Env, admin: Address, price_oracle: Address) {
-        e.storage().instance().set(&Symbol::new(&e, "admin"), &admin);
-        e.storage().instance().set(&Symbol::new(&e, "price_oracle"), &price_oracle);
-        e.storage().instance().set(&Symbol::new(&e, "next_position_id"), &1u64);
-        e.storage().instance().set(&Symbol::new(&e, "is_active"), &true);
-        e.storage().instance().set(&Symbol::new(&e, "total_xlm_balance"), &0i128);
-    }
-
-    // Transfer XLM from user to contract (internal function)
-    fn transfer_xlm_to_contract(e: &Env, from: &Address, amount: i128) {
-        // This would need to be implemented with actual XLM transfers
-        // For now, we'll simulate the transfer by updating balances
-        // In a real implementation, this would use env.invoke_contract or similar
-        
-        // Update total contract balance (simulating XLM received)
-        let total_balance: i128 = e.storage().instance()
-            .get(&Symbol::new(e, "total_xlm_balance"))
-            .unwrap_or(0);
-        e.storage().instance().set(&Symbol::new(e, "total_xlm_balance"), &(total_balance + amount));
+    pub fn initialize(e: Env, admin: Address, price_oracle: Address, collateral_token: Address) -> Result<(), Error> {
+        if e.storage().instance().has(&DataKey::Initialized) {
+            return Err(Error::AlreadyInitialized);
+        }
+
+        let config = Config {
+            version: SCHEMA_VERSION,
+            admin,
+            price_oracle,
+            collateral_token,
+            max_price_age: DEFAULT_MAX_PRICE_AGE,
+            lp_fee_share_bps: DEFAULT_LP_FEE_SHARE_BPS,
+            insurance_fee_share_bps: DEFAULT_INSURANCE_FEE_SHARE_BPS,
+            keeper_fee: DEFAULT_KEEPER_FEE,
+        };
+        e.storage().instance().set(&DataKey::Config, &config);
+        e.storage().instance().set(&DataKey::Initialized, &true);
+        e.storage().instance().set(&DataKey::NextPositionId, &1u64);
+        e.storage().instance().set(&DataKey::PauseMode, &PauseMode::Active);
+        storage::extend_instance(&e);
+        Ok(())
     }
 
-    // Transfer XLM from contract to user (internal function)
-    fn transfer_xlm_from_contract(e: &Env, to: &Address, amount: i128) {
-        // This would need to be implemented with actual XLM transfers
-        // For now, we'll simulate the transfer by updating balances
-        // In a real implementation, this would use env.invoke_contract or similar
-        
-        // Update total contract balance (simulating XLM sent)
-        let total_balance: i128 = e.storage().instance()
-            .get(&Symbol::new(e, "total_xlm_balance"))
-            .unwrap_or(0);
-        e.storage().instance().set(&Symbol::new(e, "total_xlm_balance"), &(total_balance - amount));
+    // Update the oracle settings (admin only); the collateral token cannot change
+    pub fn update_config(e: Env, admin: Address, price_oracle: Address, max_price_age: u64) -> Result<(), Error> {
+        storage::extend_instance(&e);
+        Self::check_admin(&e, &admin)?;
+
+        if max_price_age == 0 {
+            return Err(Error::InvalidConfig);
+        }
+
+        let mut config = Self::load_config(&e)?;
+        config.price_oracle = price_oracle;
+        config.max_price_age = max_price_age;
+        e.storage().instance().set(&DataKey::Config, &config);
+        events::config_updated(&e, &admin, &config);
+        Ok(())
+    }
+
+    // Nominate a new admin (admin only); takes effect once they call accept_admin
+    pub fn propose_admin(e: Env, admin: Address, new_admin: Address) -> Result<(), Error> {
+        storage::extend_instance(&e);
+        Self::check_admin(&e, &admin)?;
+        e.storage().instance().set(&DataKey::PendingAdmin, &new_admin);
+        events::admin_proposed(&e, &admin, &new_admin);
+        Ok(())
+    }
+
+    // Accept a pending admin nomination, signed by the nominee
+    pub fn accept_admin(e: Env, new_admin: Address) -> Result<(), Error> {
+        storage::extend_instance(&e);
+        let pending: Address = e.storage().instance()
+            .get(&DataKey::PendingAdmin)
+            .ok_or(Error::NoPendingAdmin)?;
+        if pending != new_admin {
+            return Err(Error::Unauthorized);
+        }
+        new_admin.require_auth();
+
+        let mut config = Self::load_config(&e)?;
+        let previous_admin = config.admin;
+        config.admin = new_admin.clone();
+        e.storage().instance().set(&DataKey::Config, &config);
+        e.storage().instance().remove(&DataKey::PendingAdmin);
+        events::admin_accepted(&e, &previous_admin, &new_admin);
+        Ok(())
+    }
+
+    // Get the admin nominee awaiting acceptance, if any
+    pub fn get_pending_admin(e: Env) -> Result<Option<Address>, Error> {
+        Ok(e.storage().instance().get(&DataKey::PendingAdmin))
+    }
+
+    // Give an account a role (admin only)
+    pub fn grant_role(e: Env, admin: Address, role: Role, account: Address) -> Result<(), Error> {
+        storage::extend_instance(&e);
+        Self::check_admin(&e, &admin)?;
+        storage::write_persistent(&e, &DataKey::Role(role, account.clone()), &true);
+        events::role_granted(&e, &admin, role, &account);
+        Ok(())
+    }
+
+    // Take a role away from an account (admin only)
+    pub fn revoke_role(e: Env, admin: Address, role: Role, account: Address) -> Result<(), Error> {
+        storage::extend_instance(&e);
+        Self::check_admin(&e, &admin)?;
+        e.storage().persistent().remove(&DataKey::Role(role, account.clone()));
+        events::role_revoked(&e, &admin, role, &account);
+        Ok(())
+    }
+
+    // Check whether an account has been granted a role
+    pub fn has_role(e: Env, role: Role, account: Address) -> Result<bool, Error> {
+        Ok(e.storage().persistent().has(&DataKey::Role(role, account)))
+    }
+
+    // Replace the contract code (admin only); storage is kept and brought up to date with migrate
+    pub fn upgrade(e: Env, admin: Address, new_wasm_hash: BytesN<32>) -> Result<(), Error> {
+        storage::extend_instance(&e);
+        Self::check_admin(&e, &admin)?;
+        e.deployer().update_current_contract_wasm(new_wasm_hash.clone());
+        events::contract_upgraded(&e, &admin, &new_wasm_hash);
+        Ok(())
+    }
+
+    // Storage schema version this code expects
+    pub fn version(_e: Env) -> Result<u32, Error> {
+        Ok(SCHEMA_VERSION)
+    }
+
+    // Bring storage written by an older version up to the current schema (admin only)
+    // Each schema change adds its conversion step here. Builds before version 2 had no upgrade
+    // entrypoint, so their storage can never reach this code and needs no step.
+    pub fn migrate(e: Env, admin: Address) -> Result<u32, Error> {
+        storage::extend_instance(&e);
+        Self::check_admin(&e, &admin)?;
+
+        let mut config = Self::load_config(&e)?;
+        if config.version > SCHEMA_VERSION {
+            return Err(Error::InvalidVersion);
+        }
+        let from_version = config.version;
+
+        config.version = SCHEMA_VERSION;
+        e.storage().instance().set(&DataKey::Config, &config);
+        events::contract_migrated(&e, &admin, from_version, SCHEMA_VERSION);
+        Ok(SCHEMA_VERSION)
+    }
+
+    // Get the contract configuration
+    pub fn get_config(e: Env) -> Result<Config, Error> {
+        Self::load_config(&e)
+    }
+
+    // Load the configuration or fail if the contract is not initialized
+    fn load_config(e: &Env) -> Result<Config, Error> {
+        e.storage().instance()
+            .get(&DataKey::Config)
+            .ok_or(Error::NotInitialized)
+    }
+
+    // Token client for the collateral asset (XLM through its Stellar Asset Contract)
+    fn collateral_client(e: &Env) -> Result<token::Client<'_>, Error> {
+        let config = Self::load_config(e)?;
+        Ok(token::Client::new(e, &config.collateral_token))
+    }
+
+    // Pull collateral from a user into the contract
+    fn transfer_to_contract(e: &Env, from: &Address, amount: i128) -> Result<(), Error> {
+        Self::collateral_client(e)?.transfer(from, &e.current_contract_address(), &amount);
+        Ok(())
+    }
+
+    // Pay collateral out of the contract to a user
+    fn transfer_from_contract(e: &Env, to: &Address, amount: i128) -> Result<(), Error> {
+        Self::collateral_client(e)?.transfer(&e.current_contract_address(), to, &amount);
+        Ok(())
     }
 
-    // Open a leveraged position with direct XLM transfer
+    // Open a leveraged position, pulling margin from the trader
+    // Reverts if the oracle price is above `acceptable_price` for a long, or below it for a short
+    // Optional stop-loss and take-profit prices close the whole position when reached
     pub fn open_position(
         e: Env,
         trader: Address,
+        market: Symbol,
         margin_amount: i128,
         leverage: u32,
-        is_long: bool
-    ) -> u64 {
-        // Validate leverage (1x to 10x)
-        if leverage < 1 || leverage > 10 {
-            panic!("Invalid leverage (1-10x only)");
+        is_long: bool,
+        acceptable_price: i128,
+        stop_loss: Option<i128>,
+        take_profit: Option<i128>
+    ) -> Result<u64, Error> {
+        storage::extend_instance(&e);
+        trader.require_auth();
+
+        let market = Self::load_market(&e, &market)?;
+        Self::validate_open(&e, &market, margin_amount, leverage)?;
+
+        // Transfer margin from user to contract
+        Self::transfer_to_contract(&e, &trader, margin_amount)?;
+
+        // Get current price from the oracle
+        let current_price = Self::get_oracle_price(&e, &market.asset)?;
+        Self::check_slippage(is_long, current_price, acceptable_price)?;
+        Self::validate_triggers(is_long, current_price, stop_loss, take_profit)?;
+
+        Self::open_funded(&e, &trader, &market, margin_amount, leverage, is_long, current_price, stop_loss, take_profit)
+    }
+
+    // Check that a new position with this margin and leverage may be opened on a market
+    fn validate_open(e: &Env, market: &Market, margin_amount: i128, leverage: u32) -> Result<(), Error> {
+        if !market.enabled {
+            return Err(Error::MarketDisabled);
+        }
+        Self::check_trading_allowed(e, &market.id, true)?;
+
+        // Validate leverage against the market limit
+        if leverage < 1 || leverage > market.max_leverage {
+            return Err(Error::InvalidLeverage);
         }
 
         // Validate margin amount
         if margin_amount < 10_000_000 { // Minimum 1 XLM
-            panic!("Minimum margin is 1 XLM");
+            return Err(Error::MarginTooLow);
         }
 
-        // Transfer XLM from user to contract as margin
-        Self::transfer_xlm_to_contract(&e, &trader, margin_amount);
+        // The open fee is charged from margin and must leave some behind
+        let open_fee = margin_amount * leverage as i128 * market.open_fee_bps as i128 / BPS_DENOMINATOR;
+        if open_fee >= margin_amount {
+            return Err(Error::MarginTooLow);
+        }
+        Ok(())
+    }
 
-        // Calculate position size
+    // Open a position at `entry_price` from margin already held by the contract
+    fn open_funded(
+        e: &Env,
+        trader: &Address,
+        market: &Market,
+        margin_amount: i128,
+        leverage: u32,
+        is_long: bool,
+        entry_price: i128,
+        stop_loss: Option<i128>,
+        take_profit: Option<i128>
+    ) -> Result<u64, Error> {
+        // Calculate position size and charge the open fee from margin
         let position_size = margin_amount * leverage as i128;
+        let open_fee = position_size * market.open_fee_bps as i128 / BPS_DENOMINATOR;
+        Self::collect_fees(e, open_fee)?;
 
-        // Get current price (mock for now)
-        let current_price = Self::get_mock_price();
+        // Bring funding up to date before the open interest changes
+        let mut state = Self::accrue_funding(e, market);
+        Self::check_open_interest_caps(e, market, &state, trader, is_long, position_size)?;
 
-        // Generate position ID
+        // Reserve the next position ID
         let position_id: u64 = e.storage().instance()
-            .get(&Symbol::new(&e, "next_position_id"))
-            .unwrap_or(0) + 1;
-        e.storage().instance().set(&Symbol::new(&e, "next_position_id"), &position_id);
-
-        // Store position data in simple storage
-        e.storage().instance().set(&Symbol::new(&e, "current_trader"), &trader);
-        e.storage().instance().set(&Symbol::new(&e, "current_margin"), &margin_amount);
-        e.storage().instance().set(&Symbol::new(&e, "current_leverage"), &(leverage as i128));
-        e.storage().instance().set(&Symbol::new(&e, "current_size"), &position_size);
-        e.storage().instance().set(&Symbol::new(&e, "current_side"), &(if is_long { 1i128 } else { -1i128 }));
-        e.storage().instance().set(&Symbol::new(&e, "current_entry_price"), &current_price);
-        e.storage().instance().set(&Symbol::new(&e, "current_timestamp"), &(e.ledger().timestamp() as i128));
-        e.storage().instance().set(&Symbol::new(&e, "current_is_active"), &1i128);
+            .get(&DataKey::NextPositionId)
+            .unwrap_or(1);
+        e.storage().instance().set(&DataKey::NextPositionId, &(position_id + 1));
+
+        // Store the position under its own key
+        let position = Position {
+            id: position_id,
+            trader: trader.clone(),
+            market: market.id.clone(),
+            margin: margin_amount - open_fee,
+            leverage,
+            size: position_size,
+            is_long,
+            entry_price,
+            funding_index: state.funding_index,
+            opened_at: e.ledger().timestamp(),
+            status: PositionStatus::Open,
+            close_price: 0,
+            closed_at: 0,
+            pnl: 0,
+            realized_pnl: 0,
+            fees_paid: open_fee,
+            stop_loss,
+            take_profit,
+            trigger_size: 0,
+        };
+        storage::write_persistent(e, &DataKey::Position(position_id), &position);
+        Self::add_open_interest(&mut state, &position);
+        storage::write_persistent(e, &DataKey::MarketState(market.id.clone()), &state);
+        Self::adjust_trader_notional(e, trader, &market.id, position_size);
+        events::position_opened(e, &position);
 
         // Update trader positions
-        let trader_positions_key = Symbol::new(&e, "trader_positions");
-        let mut trader_positions: Map<Address, Vec<u64>> = e.storage().instance()
-            .get(&trader_positions_key)
-            .unwrap_or(Map::new(&e));
-        
-        let mut positions_list = trader_positions.get(trader.clone()).unwrap_or(Vec::new(&e));
+        let trader_positions_key = DataKey::TraderPositions(trader.clone());
+        let mut positions_list: Vec<u64> = storage::read_persistent(e, &trader_positions_key)
+            .unwrap_or(Vec::new(e));
         positions_list.push_back(position_id);
-        trader_positions.set(trader.clone(), positions_list);
-        e.storage().instance().set(&trader_positions_key, &trader_positions);
+        storage::write_persistent(e, &trader_positions_key, &positions_list);
 
-        position_id
+        Ok(position_id)
+    }
+
+    // Place a limit order, escrowing its margin and the keeper fee until it is executed or cancelled
+    // The order opens `size / leverage` of margin at `leverage`; longs fill at or below
+    // `limit_price`, shorts at or above it, and it can no longer fill after `expiry`
+    pub fn place_limit_order(
+        e: Env,
+        trader: Address,
+        market: Symbol,
+        is_long: bool,
+        size: i128,
+        leverage: u32,
+        limit_price: i128,
+        expiry: u64
+    ) -> Result<u64, Error> {
+        storage::extend_instance(&e);
+        trader.require_auth();
+
+        let market = Self::load_market(&e, &market)?;
+        if leverage < 1 || size <= 0 || limit_price <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+        let margin_amount = size / leverage as i128;
+        Self::validate_open(&e, &market, margin_amount, leverage)?;
+        if expiry <= e.ledger().timestamp() {
+            return Err(Error::OrderExpired);
+        }
+
+        let keeper_fee = Self::load_config(&e)?.keeper_fee;
+        Self::transfer_to_contract(&e, &trader, margin_amount + keeper_fee)?;
+
+        Self::store_new_order(&e, Order {
+            id: 0,
+            kind: OrderKind::Limit,
+            trader,
+            market: market.id,
+            is_long,
+            margin: margin_amount,
+            leverage,
+            limit_price,
+            price_time: 0,
+            expiry,
+            keeper_fee,
+            created_at: e.ledger().timestamp(),
+            status: OrderStatus::Open,
+            position_id: 0,
+        })
+    }
+
+    // Request a market order that fills at the first oracle price published after this ledger,
+    // so the trader cannot act on a price they have already seen
+    // Unfilled requests expire `max_price_age` after that price and can then be cancelled
+    pub fn request_market_order(
+        e: Env,
+        trader: Address,
+        market: Symbol,
+        margin_amount: i128,
+        leverage: u32,
+        is_long: bool,
+        acceptable_price: i128
+    ) -> Result<u64, Error> {
+        storage::extend_instance(&e);
+        trader.require_auth();
+
+        let market = Self::load_market(&e, &market)?;
+        Self::validate_open(&e, &market, margin_amount, leverage)?;
+
+        let config = Self::load_config(&e)?;
+        let price_time = Self::next_price_time(&e, e.ledger().timestamp())?;
+        Self::transfer_to_contract(&e, &trader, margin_amount + config.keeper_fee)?;
+
+        Self::store_new_order(&e, Order {
+            id: 0,
+            kind: OrderKind::Market,
+            trader,
+            market: market.id,
+            is_long,
+            margin: margin_amount,
+            leverage,
+            limit_price: acceptable_price,
+            price_time,
+            expiry: price_time + config.max_price_age,
+            keeper_fee: config.keeper_fee,
+            created_at: e.ledger().timestamp(),
+            status: OrderStatus::Open,
+            position_id: 0,
+        })
+    }
+
+    // Assign the next order ID, store the order and add it to its trader's index
+    fn store_new_order(e: &Env, mut order: Order) -> Result<u64, Error> {
+        let order_id: u64 = e.storage().instance()
+            .get(&DataKey::NextOrderId)
+            .unwrap_or(1);
+        e.storage().instance().set(&DataKey::NextOrderId, &(order_id + 1));
+
+        order.id = order_id;
+        storage::write_persistent(e, &DataKey::Order(order_id), &order);
+        events::order_placed(e, &order);
+
+        let trader_orders_key = DataKey::TraderOrders(order.trader);
+        let mut orders_list: Vec<u64> = storage::read_persistent(e, &trader_orders_key)
+            .unwrap_or(Vec::new(e));
+        orders_list.push_back(order_id);
+        storage::write_persistent(e, &trader_orders_key, &orders_list);
+
+        Ok(order_id)
+    }
+
+    // Cancel an open order and refund its escrow to the trader
+    // Market orders can only be cancelled once they have expired unfilled, and forfeit their
+    // keeper fee to the insurance fund so letting a known fill price lapse is not free
+    pub fn cancel_order(e: Env, trader: Address, order_id: u64) -> Result<(), Error> {
+        storage::extend_instance(&e);
+        trader.require_auth();
+
+        let mut order = Self::load_order(&e, order_id)?;
+        if order.status != OrderStatus::Open {
+            return Err(Error::OrderNotOpen);
+        }
+        if order.trader != trader {
+            return Err(Error::Unauthorized);
+        }
+        if order.kind == OrderKind::Market && e.ledger().timestamp() <= order.expiry {
+            return Err(Error::OrderNotExpired);
+        }
+
+        let mut refund = order.margin + order.keeper_fee;
+        if order.kind == OrderKind::Market {
+            let insurance = Self::load_insurance_balance(&e);
+            e.storage().instance().set(&DataKey::InsuranceBalance, &(insurance + order.keeper_fee));
+            refund -= order.keeper_fee;
+        }
+
+        order.status = OrderStatus::Cancelled;
+        storage::write_persistent(&e, &DataKey::Order(order_id), &order);
+        Self::transfer_from_contract(&e, &trader, refund)?;
+        events::order_cancelled(&e, &order);
+        Ok(())
+    }
+
+    // Fill a limit order whose price has been reached, or a market order once its oracle price
+    // is published; anyone but a market order's own trader can call this and earns the keeper fee
+    pub fn execute_order(e: Env, keeper: Address, order_id: u64) -> Result<u64, Error> {
+        storage::extend_instance(&e);
+        keeper.require_auth();
+
+        let mut order = Self::load_order(&e, order_id)?;
+        if order.status != OrderStatus::Open {
+            return Err(Error::OrderNotOpen);
+        }
+        if e.ledger().timestamp() > order.expiry {
+            return Err(Error::OrderExpired);
+        }
+
+        let market = Self::load_market(&e, &order.market)?;
+        Self::validate_open(&e, &market, order.margin, order.leverage)?;
+
+        let current_price = match order.kind {
+            OrderKind::Limit => {
+                let current_price = Self::get_oracle_price(&e, &market.asset)?;
+                let crossed = if order.is_long {
+                    current_price <= order.limit_price
+                } else {
+                    current_price >= order.limit_price
+                };
+                if !crossed {
+                    return Err(Error::OrderNotTriggered);
+                }
+                current_price
+            }
+            OrderKind::Market => {
+                // The trader would otherwise choose whether to fill after seeing the price
+                if keeper == order.trader {
+                    return Err(Error::Unauthorized);
+                }
+                if e.ledger().timestamp() < order.price_time {
+                    return Err(Error::OrderNotTriggered);
+                }
+                let fill_price = Self::get_oracle_price_at(&e, &market.asset, order.price_time)?;
+                Self::check_slippage(order.is_long, fill_price, order.limit_price)?;
+                fill_price
+            }
+        };
+
+        let position_id = Self::open_funded(
+            &e,
+            &order.trader,
+            &market,
+            order.margin,
+            order.leverage,
+            order.is_long,
+            current_price,
+            None,
+            None,
+        )?;
+
+        order.status = OrderStatus::Executed;
+        order.position_id = position_id;
+        storage::write_persistent(&e, &DataKey::Order(order_id), &order);
+        if order.keeper_fee > 0 {
+            Self::transfer_from_contract(&e, &keeper, order.keeper_fee)?;
+        }
+        events::order_executed(&e, &order, &keeper);
+
+        Ok(position_id)
+    }
+
+    // Get order details by ID
+    pub fn get_order(e: Env, order_id: u64) -> Result<Order, Error> {
+        Self::load_order(&e, order_id)
+    }
+
+    // Get trader orders
+    pub fn get_trader_orders(e: Env, trader: Address) -> Result<Vec<u64>, Error> {
+        Ok(storage::read_persistent(&e, &DataKey::TraderOrders(trader))
+            .unwrap_or(Vec::new(&e)))
+    }
+
+    // Load an order record or fail if the ID is unknown
+    fn load_order(e: &Env, order_id: u64) -> Result<Order, Error> {
+        storage::read_persistent(e, &DataKey::Order(order_id))
+            .ok_or(Error::OrderNotFound)
     }
 
     // Close a position and calculate PnL
-    pub fn close_position(e: Env, trader: Address, _position_id: u64) -> i128 {
-        // Check if position is active
-        let is_active: i128 = e.storage().instance()
-            .get(&Symbol::new(&e, "current_is_active"))
-            .unwrap_or(0);
-        
-        if is_active == 0 {
-            panic!("Position already closed");
+    // Reverts if the oracle price is below `acceptable_price` for a long, or above it for a short
+    pub fn close_position(e: Env, trader: Address, position_id: u64, acceptable_price: i128) -> Result<i128, Error> {
+        storage::extend_instance(&e);
+        trader.require_auth();
+
+        let mut position = Self::load_trader_position(&e, &trader, position_id)?;
+        Self::check_trading_allowed(&e, &position.market, false)?;
+
+        // Get current price from the oracle
+        let market = Self::load_market(&e, &position.market)?;
+        let current_price = Self::get_oracle_price(&e, &market.asset)?;
+        Self::check_slippage(!position.is_long, current_price, acceptable_price)?;
+
+        let size = position.size;
+        Self::realize(&e, &mut position, &market, current_price, size)
+    }
+
+    // Close part of a position; the rest stays open at the same entry price
+    // `acceptable_price` bounds the execution price as in close_position
+    pub fn reduce_position(
+        e: Env,
+        trader: Address,
+        position_id: u64,
+        size_delta: i128,
+        acceptable_price: i128
+    ) -> Result<i128, Error> {
+        storage::extend_instance(&e);
+        trader.require_auth();
+
+        let mut position = Self::load_trader_position(&e, &trader, position_id)?;
+        Self::check_trading_allowed(&e, &position.market, false)?;
+
+        // Closing the whole size goes through close_position
+        if size_delta <= 0 || size_delta >= position.size {
+            return Err(Error::InvalidAmount);
         }
 
-        // Verify trader owns this position
-        let position_trader: Address = e.storage().instance()
-            .get(&Symbol::new(&e, "current_trader"))
-            .expect("No active position");
-        
-        if position_trader != trader {
-            panic!("Unauthorized");
+        let market = Self::load_market(&e, &position.market)?;
+        let current_price = Self::get_oracle_price(&e, &market.asset)?;
+        Self::check_slippage(!position.is_long, current_price, acceptable_price)?;
+        Self::realize(&e, &mut position, &market, current_price, size_delta)
+    }
+
+    // Close `size_delta` of a position at `current_price` and pay the trader out
+    // PnL, accrued funding and margin are realized pro rata; returns the realized PnL
+    fn realize(
+        e: &Env,
+        position: &mut Position,
+        market: &Market,
+        current_price: i128,
+        size_delta: i128
+    ) -> Result<i128, Error> {
+        let mut state = Self::accrue_funding(e, market);
+        let total_pnl = Self::unrealized_pnl(position, current_price)
+            - Self::accrued_funding(position, state.funding_index);
+        // Profits are paid out of the LP vault and never out of other traders' collateral
+        let pnl = (total_pnl * size_delta / position.size).min(Self::load_pool_balance(e).max(0));
+        let released_margin = position.margin * size_delta / position.size;
+        Self::remove_open_interest(&mut state, position);
+
+        // Losses beyond the margin cannot be collected from the trader
+        let equity = released_margin + pnl;
+        if equity < 0 {
+            Self::absorb_shortfall(e, position.id, -equity);
         }
 
-        // Get position details
-        let margin: i128 = e.storage().instance()
-            .get(&Symbol::new(&e, "current_margin"))
-            .unwrap_or(0);
-        let leverage: i128 = e.storage().instance()
-            .get(&Symbol::new(&e, "current_leverage"))
-            .unwrap_or(1);
-        let position_size: i128 = e.storage().instance()
-            .get(&Symbol::new(&e, "current_size"))
-            .unwrap_or(0);
-        let side: i128 = e.storage().instance()
-            .get(&Symbol::new(&e, "current_side"))
-            .unwrap_or(1);
-        let entry_price: i128 = e.storage().instance()
-            .get(&Symbol::new(&e, "current_entry_price"))
-            .unwrap_or(0);
+        let close_fee = (size_delta * market.close_fee_bps as i128 / BPS_DENOMINATOR).min(equity.max(0));
+        let payout_amount = equity.max(0) - close_fee;
+        Self::collect_fees(e, close_fee)?;
+        Self::settle_with_pool(e, released_margin - equity.max(0));
 
-        // Get current price (mock for now)
-        let current_price = Self::get_mock_price();
+        position.size -= size_delta;
+        position.margin -= released_margin;
+        position.realized_pnl += pnl;
+        position.fees_paid += close_fee;
+        if position.size == 0 {
+            position.status = PositionStatus::Closed;
+            position.close_price = current_price;
+            position.closed_at = e.ledger().timestamp();
+            position.pnl = position.realized_pnl;
+        } else {
+            // The remaining size keeps its entry price and funding snapshot
+            Self::add_open_interest(&mut state, position);
+        }
+        storage::write_persistent(e, &DataKey::MarketState(market.id.clone()), &state);
+        storage::write_persistent(e, &DataKey::Position(position.id), position);
+        Self::adjust_trader_notional(e, &position.trader, &market.id, -size_delta);
+
+        // Transfer collateral back to trader (margin + PnL, less fees)
+        if payout_amount > 0 {
+            Self::transfer_from_contract(e, &position.trader, payout_amount)?;
+        }
+        if position.size == 0 {
+            events::position_closed(e, position, payout_amount);
+        } else {
+            events::position_reduced(e, position, size_delta, pnl, payout_amount);
+        }
+
+        Ok(pnl)
+    }
 
-        // Calculate PnL
-        let price_diff = current_price - entry_price;
-        let pnl = if side > 0 { // long position
-            (price_diff * position_size) / entry_price
-        } else { // short position
-            (-price_diff * position_size) / entry_price
+    // Post more collateral to an open position
+    pub fn add_margin(e: Env, trader: Address, position_id: u64, amount: i128) -> Result<(), Error> {
+        storage::extend_instance(&e);
+        trader.require_auth();
+
+        let mut position = Self::load_trader_position(&e, &trader, position_id)?;
+        Self::check_trading_allowed(&e, &position.market, false)?;
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        Self::transfer_to_contract(&e, &trader, amount)?;
+        position.margin += amount;
+        position.leverage = Self::effective_leverage(&position);
+        storage::write_persistent(&e, &DataKey::Position(position_id), &position);
+        events::margin_changed(&e, &position, amount);
+        Ok(())
+    }
+
+    // Withdraw collateral from an open position, keeping it above the initial margin requirement
+    pub fn remove_margin(e: Env, trader: Address, position_id: u64, amount: i128) -> Result<(), Error> {
+        storage::extend_instance(&e);
+        trader.require_auth();
+
+        let mut position = Self::load_trader_position(&e, &trader, position_id)?;
+        Self::check_trading_allowed(&e, &position.market, true)?;
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let market = Self::load_market(&e, &position.market)?;
+        let current_price = Self::get_oracle_price(&e, &market.asset)?;
+        let funding_index = Self::current_funding_index(&e, &market, &Self::load_market_state(&e, &market.id));
+        let pnl = Self::unrealized_pnl(&position, current_price)
+            - Self::accrued_funding(&position, funding_index);
+
+        // Unrealized profit cannot be withdrawn, but losses count against the margin
+        position.margin -= amount;
+        if position.margin + pnl.min(0) < Self::initial_margin(position.size, &market) {
+            return Err(Error::MarginTooLow);
+        }
+
+        position.leverage = Self::effective_leverage(&position);
+        storage::write_persistent(&e, &DataKey::Position(position_id), &position);
+        Self::transfer_from_contract(&e, &trader, amount)?;
+        events::margin_changed(&e, &position, -amount);
+        Ok(())
+    }
+
+    // Add size to an open position, pulling extra margin from the trader
+    // Accrued funding is settled into the margin first, and the entry price becomes the
+    // size-weighted (harmonic) average so the PnL of the existing size is preserved
+    // `acceptable_price` bounds the execution price as in open_position
+    pub fn increase_position(
+        e: Env,
+        trader: Address,
+        position_id: u64,
+        margin_amount: i128,
+        leverage: u32,
+        acceptable_price: i128
+    ) -> Result<(), Error> {
+        storage::extend_instance(&e);
+        trader.require_auth();
+
+        let mut position = Self::load_trader_position(&e, &trader, position_id)?;
+        let market = Self::load_market(&e, &position.market)?;
+        if !market.enabled {
+            return Err(Error::MarketDisabled);
+        }
+        Self::check_trading_allowed(&e, &market.id, true)?;
+
+        if leverage < 1 || leverage > market.max_leverage {
+            return Err(Error::InvalidLeverage);
+        }
+        if margin_amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let size_delta = margin_amount * leverage as i128;
+        let open_fee = size_delta * market.open_fee_bps as i128 / BPS_DENOMINATOR;
+        if open_fee >= margin_amount {
+            return Err(Error::MarginTooLow);
+        }
+
+        let current_price = Self::get_oracle_price(&e, &market.asset)?;
+        Self::check_slippage(position.is_long, current_price, acceptable_price)?;
+        let mut state = Self::accrue_funding(&e, &market);
+        Self::check_open_interest_caps(&e, &market, &state, &trader, position.is_long, size_delta)?;
+        Self::remove_open_interest(&mut state, &position);
+
+        // Settle funding against the pool, paying out no more than it holds,
+        // and restart accrual from the current index
+        let funding = Self::accrued_funding(&position, state.funding_index)
+            .max(-Self::load_pool_balance(&e).max(0));
+        Self::settle_with_pool(&e, funding);
+        position.margin -= funding;
+        position.realized_pnl -= funding;
+        position.funding_index = state.funding_index;
+
+        // Size-weighted harmonic mean of the two entry prices, (s1 + s2) * p1 * p2 / (s1 * p2 + s2 * p1),
+        // in 256-bit arithmetic so 14-decimal prices neither overflow nor round away
+        let held = I256::from_i128(&e, position.size);
+        let added = I256::from_i128(&e, size_delta);
+        let entry = I256::from_i128(&e, position.entry_price);
+        let price = I256::from_i128(&e, current_price);
+        let weighted = held.mul(&price).add(&added.mul(&entry));
+        position.entry_price = held.add(&added).mul(&entry).mul(&price).div(&weighted)
+            .to_i128()
+            .ok_or(Error::InvalidAmount)?;
+        position.size += size_delta;
+        position.margin += margin_amount - open_fee;
+        position.fees_paid += open_fee;
+
+        let pnl = Self::unrealized_pnl(&position, current_price);
+        if position.margin + pnl.min(0) < Self::initial_margin(position.size, &market) {
+            return Err(Error::MarginTooLow);
+        }
+
+        Self::transfer_to_contract(&e, &trader, margin_amount)?;
+        Self::collect_fees(&e, open_fee)?;
+
+        position.leverage = Self::effective_leverage(&position);
+        storage::write_persistent(&e, &DataKey::Position(position_id), &position);
+        Self::add_open_interest(&mut state, &position);
+        storage::write_persistent(&e, &DataKey::MarketState(market.id.clone()), &state);
+        Self::adjust_trader_notional(&e, &trader, &market.id, size_delta);
+        events::position_increased(&e, &position, size_delta);
+        Ok(())
+    }
+
+    // Load an open position on behalf of its owner
+    fn load_trader_position(e: &Env, trader: &Address, position_id: u64) -> Result<Position, Error> {
+        let position = Self::load_position(e, position_id)?;
+        if position.status != PositionStatus::Open {
+            return Err(Error::PositionClosed);
+        }
+        if position.trader != *trader {
+            return Err(Error::Unauthorized);
+        }
+        Ok(position)
+    }
+
+    // Leverage implied by a position's current size and margin
+    fn effective_leverage(position: &Position) -> u32 {
+        if position.margin <= 0 {
+            return u32::MAX;
+        }
+        (position.size / position.margin).clamp(1, u32::MAX as i128) as u32
+    }
+
+    // Set or clear a position's stop-loss and take-profit prices
+    // When triggered, `trigger_size` of the position is closed (0 or the full size closes it all)
+    pub fn set_triggers(
+        e: Env,
+        trader: Address,
+        position_id: u64,
+        stop_loss: Option<i128>,
+        take_profit: Option<i128>,
+        trigger_size: i128
+    ) -> Result<(), Error> {
+        storage::extend_instance(&e);
+        trader.require_auth();
+
+        let mut position = Self::load_trader_position(&e, &trader, position_id)?;
+        Self::check_trading_allowed(&e, &position.market, false)?;
+        if trigger_size < 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let market = Self::load_market(&e, &position.market)?;
+        let current_price = Self::get_oracle_price(&e, &market.asset)?;
+        Self::validate_triggers(position.is_long, current_price, stop_loss, take_profit)?;
+
+        position.stop_loss = stop_loss;
+        position.take_profit = take_profit;
+        position.trigger_size = trigger_size;
+        storage::write_persistent(&e, &DataKey::Position(position_id), &position);
+        events::triggers_updated(&e, &position);
+        Ok(())
+    }
+
+    // Execute a position's stop-loss or take-profit once the oracle price has crossed it
+    // Anyone can call this; it fails unless a trigger condition is met
+    pub fn trigger(e: Env, position_id: u64) -> Result<i128, Error> {
+        storage::extend_instance(&e);
+
+        let mut position = Self::load_position(&e, position_id)?;
+        if position.status != PositionStatus::Open {
+            return Err(Error::PositionClosed);
+        }
+        Self::check_trading_allowed(&e, &position.market, false)?;
+
+        let market = Self::load_market(&e, &position.market)?;
+        let current_price = Self::get_oracle_price(&e, &market.asset)?;
+
+        let stop_hit = match position.stop_loss {
+            Some(price) if position.is_long => current_price <= price,
+            Some(price) => current_price >= price,
+            None => false,
+        };
+        let profit_hit = match position.take_profit {
+            Some(price) if position.is_long => current_price >= price,
+            Some(price) => current_price <= price,
+            None => false,
         };
+        if !stop_hit && !profit_hit {
+            return Err(Error::TriggerNotMet);
+        }
+
+        // A partial close disarms the trigger that fired so it cannot repeat at the same level
+        let size_delta = if position.trigger_size > 0 && position.trigger_size < position.size {
+            if stop_hit {
+                position.stop_loss = None;
+            } else {
+                position.take_profit = None;
+            }
+            position.trigger_size
+        } else {
+            position.size
+        };
+        events::position_triggered(&e, &position, current_price, stop_hit);
+        Self::realize(&e, &mut position, &market, current_price, size_delta)
+    }
 
-        let total_payout = margin + pnl;
+    // Reject an execution price worse than the trader's bound: a maximum when buying, a minimum when selling
+    fn check_slippage(is_buy: bool, execution_price: i128, acceptable_price: i128) -> Result<(), Error> {
+        if (is_buy && execution_price > acceptable_price) || (!is_buy && execution_price < acceptable_price) {
+            return Err(Error::SlippageExceeded);
+        }
+        Ok(())
+    }
+
+    // Triggers must sit on the correct side of the current price, or they would fire immediately
+    fn validate_triggers(
+        is_long: bool,
+        current_price: i128,
+        stop_loss: Option<i128>,
+        take_profit: Option<i128>
+    ) -> Result<(), Error> {
+        if let Some(price) = stop_loss {
+            if price <= 0 || (is_long && price >= current_price) || (!is_long && price <= current_price) {
+                return Err(Error::InvalidTrigger);
+            }
+        }
+        if let Some(price) = take_profit {
+            if price <= 0 || (is_long && price <= current_price) || (!is_long && price >= current_price) {
+                return Err(Error::InvalidTrigger);
+            }
+        }
+        Ok(())
+    }
+
+    // Liquidate a position whose equity fell below the maintenance margin
+    // Anyone can call this; the keeper receives its share of the liquidation fee
+    pub fn liquidate(e: Env, keeper: Address, position_id: u64) -> Result<i128, Error> {
+        storage::extend_instance(&e);
+        keeper.require_auth();
+
+        let mut position = Self::load_position(&e, position_id)?;
+        if position.status != PositionStatus::Open {
+            return Err(Error::PositionClosed);
+        }
+        Self::check_trading_allowed(&e, &position.market, false)?;
+
+        let market = Self::load_market(&e, &position.market)?;
+        let current_price = Self::get_oracle_price(&e, &market.asset)?;
+
+        let mut state = Self::accrue_funding(&e, &market);
+        let pnl = Self::unrealized_pnl(&position, current_price)
+            - Self::accrued_funding(&position, state.funding_index);
+        let equity = position.margin + pnl;
+        if equity >= Self::maintenance_margin(&position, &market) {
+            return Err(Error::NotLiquidatable);
+        }
+        Self::remove_open_interest(&mut state, &position);
+        storage::write_persistent(&e, &DataKey::MarketState(market.id.clone()), &state);
+        Self::adjust_trader_notional(&e, &position.trader, &market.id, -position.size);
+
+        if equity < 0 {
+            Self::absorb_shortfall(&e, position_id, -equity);
+        }
 
-        // Mark position as closed
-        e.storage().instance().set(&Symbol::new(&e, "current_is_active"), &0i128);
-        e.storage().instance().set(&Symbol::new(&e, "current_close_price"), &current_price);
-        e.storage().instance().set(&Symbol::new(&e, "current_close_timestamp"), &(e.ledger().timestamp() as i128));
-        e.storage().instance().set(&Symbol::new(&e, "current_pnl"), &pnl);
+        // The liquidation fee comes out of whatever equity is left
+        let remaining = equity.max(0);
+        let liquidation_fee = (position.size * market.liquidation_fee_bps as i128 / BPS_DENOMINATOR).min(remaining);
+        let keeper_reward = liquidation_fee * market.keeper_reward_bps as i128 / BPS_DENOMINATOR;
+        let trader_refund = remaining - liquidation_fee;
+        Self::collect_fees(&e, liquidation_fee - keeper_reward)?;
+        Self::settle_with_pool(&e, position.margin - remaining);
 
-        // Transfer XLM back to trader (margin + PnL)
-        let payout_amount = if total_payout > 0 {
-            total_payout
+        position.status = PositionStatus::Liquidated;
+        position.close_price = current_price;
+        position.closed_at = e.ledger().timestamp();
+        position.realized_pnl += pnl;
+        position.fees_paid += liquidation_fee;
+        position.pnl = position.realized_pnl;
+        storage::write_persistent(&e, &DataKey::Position(position_id), &position);
+
+        if keeper_reward > 0 {
+            Self::transfer_from_contract(&e, &keeper, keeper_reward)?;
+        }
+        if trader_refund > 0 {
+            Self::transfer_from_contract(&e, &position.trader, trader_refund)?;
+        }
+        events::position_liquidated(&e, &position, &keeper, keeper_reward);
+
+        Ok(keeper_reward)
+    }
+
+    // Price at which a position reaches its maintenance margin
+    pub fn get_liquidation_price(e: Env, position_id: u64) -> Result<i128, Error> {
+        let position = Self::load_position(&e, position_id)?;
+        if position.status != PositionStatus::Open {
+            return Err(Error::PositionClosed);
+        }
+        let market = Self::load_market(&e, &position.market)?;
+
+        // Solve margin - funding + size * (price - entry) / entry = maintenance margin for price
+        let funding_index = Self::current_funding_index(&e, &market, &Self::load_market_state(&e, &market.id));
+        let buffer = position.margin
+            - Self::accrued_funding(&position, funding_index)
+            - Self::maintenance_margin(&position, &market);
+        let price_move = position.entry_price * buffer / position.size;
+        if position.is_long {
+            Ok((position.entry_price - price_move).max(0))
         } else {
-            margin // Return at least the margin if PnL is negative
+            Ok(position.entry_price + price_move)
+        }
+    }
+
+    // Check whether a position can be liquidated at the current oracle price
+    pub fn is_liquidatable(e: Env, position_id: u64) -> Result<bool, Error> {
+        let position = Self::load_position(&e, position_id)?;
+        if position.status != PositionStatus::Open {
+            return Ok(false);
+        }
+
+        let market = Self::load_market(&e, &position.market)?;
+        let current_price = Self::get_oracle_price(&e, &market.asset)?;
+        let funding_index = Self::current_funding_index(&e, &market, &Self::load_market_state(&e, &market.id));
+        let equity = position.margin
+            + Self::unrealized_pnl(&position, current_price)
+            - Self::accrued_funding(&position, funding_index);
+
+        Ok(equity < Self::maintenance_margin(&position, &market))
+    }
+
+    // Accrue funding for a market up to the current ledger time; callable by keepers
+    pub fn settle_funding(e: Env, market: Symbol) -> Result<i128, Error> {
+        storage::extend_instance(&e);
+        let market = Self::load_market(&e, &market)?;
+        Self::check_trading_allowed(&e, &market.id, false)?;
+        let state = Self::accrue_funding(&e, &market);
+        events::funding_settled(&e, &market.id, &state);
+        Ok(state.funding_index)
+    }
+
+    // Get open interest and funding state of a market
+    pub fn get_market_state(e: Env, market: Symbol) -> Result<MarketState, Error> {
+        Self::load_market(&e, &market)?;
+        Ok(Self::load_market_state(&e, &market))
+    }
+
+    // Funding a position owes at the given index (negative when it is owed funding)
+    fn accrued_funding(position: &Position, funding_index: i128) -> i128 {
+        let owed = position.size * (funding_index - position.funding_index) / FUNDING_PRECISION;
+        if position.is_long { owed } else { -owed }
+    }
+
+    // Funding index including accrual since the last update, without persisting it
+    fn current_funding_index(e: &Env, market: &Market, state: &MarketState) -> i128 {
+        let elapsed = e.ledger().timestamp().saturating_sub(state.last_funding_time) as i128;
+        let total_open_interest = state.long_open_interest + state.short_open_interest;
+        if elapsed == 0 || total_open_interest == 0 {
+            return state.funding_index;
+        }
+
+        // The heavier side pays, proportionally to the skew between longs and shorts
+        let skew = state.long_open_interest - state.short_open_interest;
+        state.funding_index
+            + skew * market.funding_rate_bps as i128 * FUNDING_PRECISION * elapsed
+                / (total_open_interest * BPS_DENOMINATOR * SECONDS_PER_DAY)
+    }
+
+    // Bring a market's funding index up to date and persist it
+    fn accrue_funding(e: &Env, market: &Market) -> MarketState {
+        let mut state = Self::load_market_state(e, &market.id);
+        state.funding_index = Self::current_funding_index(e, market, &state);
+        state.last_funding_time = e.ledger().timestamp();
+        storage::write_persistent(e, &DataKey::MarketState(market.id.clone()), &state);
+        state
+    }
+
+    // Position units used to mark a side's open interest at a new price
+    fn position_units(position: &Position) -> i128 {
+        position.size * UNIT_PRECISION / position.entry_price
+    }
+
+    // Add an opened position to its market's open interest aggregates
+    fn add_open_interest(state: &mut MarketState, position: &Position) {
+        let units = Self::position_units(position);
+        let funding_weight = position.size * position.funding_index;
+        if position.is_long {
+            state.long_open_interest += position.size;
+            state.long_units += units;
+            state.long_funding_weight += funding_weight;
+        } else {
+            state.short_open_interest += position.size;
+            state.short_units += units;
+            state.short_funding_weight += funding_weight;
+        }
+    }
+
+    // Release a closed position from the market open interest aggregates
+    fn remove_open_interest(state: &mut MarketState, position: &Position) {
+        let units = Self::position_units(position);
+        let funding_weight = position.size * position.funding_index;
+        if position.is_long {
+            state.long_open_interest -= position.size;
+            state.long_units -= units;
+            state.long_funding_weight -= funding_weight;
+        } else {
+            state.short_open_interest -= position.size;
+            state.short_units -= units;
+            state.short_funding_weight -= funding_weight;
+        }
+    }
+
+    // Reject new size that would take the market or the trader past their open interest caps
+    fn check_open_interest_caps(
+        e: &Env,
+        market: &Market,
+        state: &MarketState,
+        trader: &Address,
+        is_long: bool,
+        size_delta: i128
+    ) -> Result<(), Error> {
+        let (long_capacity, short_capacity) = Self::open_interest_capacity(market, state);
+        let capacity = if is_long { long_capacity } else { short_capacity };
+        if size_delta > capacity {
+            return Err(Error::OpenInterestCapExceeded);
+        }
+
+        if market.max_trader_notional > 0
+            && Self::load_trader_notional(e, trader, &market.id) + size_delta > market.max_trader_notional
+        {
+            return Err(Error::TraderNotionalCapExceeded);
+        }
+        Ok(())
+    }
+
+    // Size that longs and shorts can still add before hitting the market caps
+    fn open_interest_capacity(market: &Market, state: &MarketState) -> (i128, i128) {
+        let total = state.long_open_interest + state.short_open_interest;
+        let total_capacity = if market.max_open_interest > 0 {
+            market.max_open_interest - total
+        } else {
+            i128::MAX
         };
-        
-        Self::transfer_xlm_from_contract(&e, &trader, payout_amount);
+        let side_capacity = |open_interest: i128| {
+            if market.max_side_open_interest > 0 {
+                (market.max_side_open_interest - open_interest).min(total_capacity).max(0)
+            } else {
+                total_capacity.max(0)
+            }
+        };
+        (side_capacity(state.long_open_interest), side_capacity(state.short_open_interest))
+    }
 
-        pnl
+    // Read a trader's open size in a market
+    fn load_trader_notional(e: &Env, trader: &Address, market: &Symbol) -> i128 {
+        storage::read_persistent(e, &DataKey::TraderNotional(trader.clone(), market.clone()))
+            .unwrap_or(0)
     }
 
-    // Withdraw XLM from contract (admin only)
-    pub fn withdraw_contract_balance(e: Env, admin: Address, amount: i128) -> bool {
-        // Verify admin
-        let stored_admin: Address = e.storage().instance()
-            .get(&Symbol::new(&e, "admin"))
-            .expect("Contract not initialized");
-        
-        if stored_admin != admin {
-            panic!("Unauthorized - admin only");
+    // Track a trader's open size in a market as positions grow and shrink
+    fn adjust_trader_notional(e: &Env, trader: &Address, market: &Symbol, delta: i128) {
+        let key = DataKey::TraderNotional(trader.clone(), market.clone());
+        let notional = Self::load_trader_notional(e, trader, market) + delta;
+        if notional > 0 {
+            storage::write_persistent(e, &key, &notional);
+        } else {
+            e.storage().persistent().remove(&key);
         }
+    }
+
+    // Get a market's open interest and the size each side can still add
+    pub fn get_open_interest(e: Env, market: Symbol) -> Result<OpenInterest, Error> {
+        let market = Self::load_market(&e, &market)?;
+        let state = Self::load_market_state(&e, &market.id);
+        let (long_capacity, short_capacity) = Self::open_interest_capacity(&market, &state);
+        Ok(OpenInterest {
+            long_open_interest: state.long_open_interest,
+            short_open_interest: state.short_open_interest,
+            long_capacity,
+            short_capacity,
+        })
+    }
+
+    // Get a trader's open size in a market
+    pub fn get_trader_notional(e: Env, trader: Address, market: Symbol) -> Result<i128, Error> {
+        Ok(Self::load_trader_notional(&e, &trader, &market))
+    }
+
+    // Net PnL of all open positions in a market at the given price and funding index
+    fn market_unrealized_pnl(state: &MarketState, price: i128, funding_index: i128) -> i128 {
+        let long_pnl = state.long_units * price / UNIT_PRECISION - state.long_open_interest;
+        let short_pnl = state.short_open_interest - state.short_units * price / UNIT_PRECISION;
+        let long_funding = (state.long_open_interest * funding_index - state.long_funding_weight) / FUNDING_PRECISION;
+        let short_funding = (state.short_open_interest * funding_index - state.short_funding_weight) / FUNDING_PRECISION;
+        long_pnl + short_pnl - long_funding + short_funding
+    }
+
+    // Load a market's state, starting from an empty one
+    fn load_market_state(e: &Env, market: &Symbol) -> MarketState {
+        storage::read_persistent(e, &DataKey::MarketState(market.clone()))
+            .unwrap_or(MarketState {
+                long_open_interest: 0,
+                short_open_interest: 0,
+                long_units: 0,
+                short_units: 0,
+                long_funding_weight: 0,
+                short_funding_weight: 0,
+                funding_index: 0,
+                last_funding_time: e.ledger().timestamp(),
+            })
+    }
 
-        // Check contract balance
-        let total_balance: i128 = e.storage().instance()
-            .get(&Symbol::new(&e, "total_xlm_balance"))
+    // Get losses that exceeded position margins and the insurance fund, absorbed by the LP pool
+    pub fn get_bad_debt(e: Env) -> Result<i128, Error> {
+        Ok(Self::load_bad_debt(&e))
+    }
+
+    // Read the bad debt ledger
+    fn load_bad_debt(e: &Env) -> i128 {
+        e.storage().instance()
+            .get(&DataKey::BadDebt)
+            .unwrap_or(0)
+    }
+
+    // Add an uncollectable shortfall to the bad debt ledger
+    fn record_bad_debt(e: &Env, shortfall: i128) {
+        let bad_debt = Self::load_bad_debt(e);
+        e.storage().instance().set(&DataKey::BadDebt, &(bad_debt + shortfall));
+    }
+
+    // Cover a position's shortfall from the insurance fund; what it cannot cover is bad debt
+    // borne by the LP pool, which has already been credited only the position's margin
+    fn absorb_shortfall(e: &Env, position_id: u64, shortfall: i128) {
+        let insurance = Self::load_insurance_balance(e);
+        let covered = shortfall.min(insurance);
+        if covered > 0 {
+            e.storage().instance().set(&DataKey::InsuranceBalance, &(insurance - covered));
+            Self::settle_with_pool(e, covered);
+        }
+        if shortfall > covered {
+            Self::record_bad_debt(e, shortfall - covered);
+        }
+
+        let index: u64 = e.storage().instance()
+            .get(&DataKey::InsuranceCoverageCount)
             .unwrap_or(0);
-        
-        if total_balance < amount {
-            panic!("Insufficient contract balance");
+        let coverage = InsuranceCoverage {
+            position_id,
+            shortfall,
+            covered,
+            timestamp: e.ledger().timestamp(),
+        };
+        storage::write_persistent(e, &DataKey::InsuranceCoverage(index), &coverage);
+        e.storage().instance().set(&DataKey::InsuranceCoverageCount, &(index + 1));
+        events::shortfall_covered(e, &coverage);
+    }
+
+    // Read the insurance fund balance
+    fn load_insurance_balance(e: &Env) -> i128 {
+        e.storage().instance()
+            .get(&DataKey::InsuranceBalance)
+            .unwrap_or(0)
+    }
+
+    // Top up the insurance fund; anyone can contribute
+    pub fn fund_insurance(e: Env, funder: Address, amount: i128) -> Result<(), Error> {
+        storage::extend_instance(&e);
+        funder.require_auth();
+
+        if Self::load_pause_mode(&e) == PauseMode::Halted {
+            return Err(Error::ContractPaused);
+        }
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
         }
 
-        // Transfer XLM from contract to admin
-        Self::transfer_xlm_from_contract(&e, &admin, amount);
+        Self::transfer_to_contract(&e, &funder, amount)?;
+        let insurance = Self::load_insurance_balance(&e);
+        e.storage().instance().set(&DataKey::InsuranceBalance, &(insurance + amount));
+        events::insurance_funded(&e, &funder, amount);
+        Ok(())
+    }
+
+    // Get the insurance fund balance
+    pub fn get_insurance_balance(e: Env) -> Result<i128, Error> {
+        Ok(Self::load_insurance_balance(&e))
+    }
+
+    // Get the number of recorded shortfalls
+    pub fn get_insurance_coverage_count(e: Env) -> Result<u64, Error> {
+        Ok(e.storage().instance()
+            .get(&DataKey::InsuranceCoverageCount)
+            .unwrap_or(0))
+    }
 
-        true
+    // Get a recorded shortfall and how much of it the insurance fund covered
+    pub fn get_insurance_coverage(e: Env, index: u64) -> Result<InsuranceCoverage, Error> {
+        storage::read_persistent(&e, &DataKey::InsuranceCoverage(index))
+            .ok_or(Error::CoverageNotFound)
     }
 
-    // Get contract balance
-    pub fn get_contract_balance(e: Env) -> i128 {
+    // Get trading and liquidation fees collected by the protocol and not yet withdrawn
+    pub fn get_protocol_fees(e: Env) -> Result<i128, Error> {
+        Ok(Self::load_protocol_fees(&e))
+    }
+
+    // Read the protocol fee ledger
+    fn load_protocol_fees(e: &Env) -> i128 {
         e.storage().instance()
-            .get(&Symbol::new(&e, "total_xlm_balance"))
+            .get(&DataKey::ProtocolFees)
             .unwrap_or(0)
     }
 
-    // Get current position details
-    pub fn get_current_position(e: Env) -> (Address, i128, i128, i128, i128, i128, i128, i128) {
-        let trader: Address = e.storage().instance()
-            .get(&Symbol::new(&e, "current_trader"))
-            .expect("No active position");
-        let margin: i128 = e.storage().instance()
-            .get(&Symbol::new(&e, "current_margin"))
-            .unwrap_or(0);
-        let leverage: i128 = e.storage().instance()
-            .get(&Symbol::new(&e, "current_leverage"))
-            .unwrap_or(0);
-        let size: i128 = e.storage().instance()
-            .get(&Symbol::new(&e, "current_size"))
-            .unwrap_or(0);
-        let side: i128 = e.storage().instance()
-            .get(&Symbol::new(&e, "current_side"))
-            .unwrap_or(0);
-        let entry_price: i128 = e.storage().instance()
-            .get(&Symbol::new(&e, "current_entry_price"))
-            .unwrap_or(0);
-        let timestamp: i128 = e.storage().instance()
-            .get(&Symbol::new(&e, "current_timestamp"))
-            .unwrap_or(0);
-        let is_active: i128 = e.storage().instance()
-            .get(&Symbol::new(&e, "current_is_active"))
-            .unwrap_or(0);
+    // Split collected fees between the LP vault, the insurance fund and the protocol fee ledger
+    fn collect_fees(e: &Env, amount: i128) -> Result<(), Error> {
+        if amount <= 0 {
+            return Ok(());
+        }
+        let config = Self::load_config(e)?;
+        let lp_share = amount * config.lp_fee_share_bps as i128 / BPS_DENOMINATOR;
+        let insurance_share = amount * config.insurance_fee_share_bps as i128 / BPS_DENOMINATOR;
+        Self::settle_with_pool(e, lp_share);
+
+        let insurance = Self::load_insurance_balance(e);
+        e.storage().instance().set(&DataKey::InsuranceBalance, &(insurance + insurance_share));
+
+        let fees = Self::load_protocol_fees(e);
+        e.storage().instance().set(&DataKey::ProtocolFees, &(fees + amount - lp_share - insurance_share));
+        Ok(())
+    }
+
+    // Credit (or debit, when negative) the LP vault's realized balance
+    fn settle_with_pool(e: &Env, amount: i128) {
+        let balance = Self::load_pool_balance(e);
+        e.storage().instance().set(&DataKey::PoolBalance, &(balance + amount));
+    }
+
+    // Read the LP vault's realized balance: deposits, fee share and settled trader PnL
+    fn load_pool_balance(e: &Env) -> i128 {
+        e.storage().instance()
+            .get(&DataKey::PoolBalance)
+            .unwrap_or(0)
+    }
+
+    // Read the total supply of LP shares
+    fn load_total_shares(e: &Env) -> i128 {
+        e.storage().instance()
+            .get(&DataKey::TotalShares)
+            .unwrap_or(0)
+    }
+
+    // Pool value: realized balance less what open positions are owed at current prices
+    fn pool_nav(e: &Env) -> Result<i128, Error> {
+        let mut trader_pnl = 0;
+        for market_id in Self::get_markets(e.clone())?.iter() {
+            let state = Self::load_market_state(e, &market_id);
+            if state.long_open_interest == 0 && state.short_open_interest == 0 {
+                continue;
+            }
+            let market = Self::load_market(e, &market_id)?;
+            let price = Self::get_oracle_price(e, &market.asset)?;
+            let funding_index = Self::current_funding_index(e, &market, &state);
+            trader_pnl += Self::market_unrealized_pnl(&state, price, funding_index);
+        }
+        Ok(Self::load_pool_balance(e) - trader_pnl)
+    }
+
+    // Deposit collateral into the LP vault, minting shares at the current NAV
+    pub fn deposit_liquidity(e: Env, provider: Address, amount: i128) -> Result<i128, Error> {
+        storage::extend_instance(&e);
+        provider.require_auth();
+
+        if Self::load_pause_mode(&e) == PauseMode::Halted {
+            return Err(Error::ContractPaused);
+        }
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        // Whatever the pool holds before it has any LPs (fee shares, settled trader losses)
+        // belongs to no one, so it moves to the insurance fund and the first depositor
+        // is minted shares one for one against an empty pool
+        let total_shares = Self::load_total_shares(&e);
+        let shares = if total_shares == 0 {
+            let unowned = Self::load_pool_balance(&e);
+            if unowned > 0 {
+                Self::settle_with_pool(&e, -unowned);
+                let insurance = Self::load_insurance_balance(&e);
+                e.storage().instance().set(&DataKey::InsuranceBalance, &(insurance + unowned));
+                events::insurance_funded(&e, &e.current_contract_address(), unowned);
+            }
+            amount
+        } else {
+            let nav = Self::pool_nav(&e)?;
+            if nav <= 0 {
+                return Err(Error::InsufficientLiquidity);
+            }
+            amount * total_shares / nav
+        };
+        if shares <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        Self::transfer_to_contract(&e, &provider, amount)?;
+        Self::settle_with_pool(&e, amount);
+        e.storage().instance().set(&DataKey::TotalShares, &(total_shares + shares));
+        let shares_key = DataKey::LpShares(provider.clone());
+        let balance: i128 = storage::read_persistent(&e, &shares_key).unwrap_or(0);
+        storage::write_persistent(&e, &shares_key, &(balance + shares));
+        events::liquidity_deposited(&e, &provider, amount, shares);
+
+        Ok(shares)
+    }
+
+    // Burn LP shares for their value at the current NAV
+    // Only the vault's realized balance can be paid out, never trader margin
+    pub fn withdraw_liquidity(e: Env, provider: Address, shares: i128) -> Result<i128, Error> {
+        storage::extend_instance(&e);
+        provider.require_auth();
+
+        if Self::load_pause_mode(&e) == PauseMode::Halted {
+            return Err(Error::ContractPaused);
+        }
+        let shares_key = DataKey::LpShares(provider.clone());
+        let balance: i128 = storage::read_persistent(&e, &shares_key).unwrap_or(0);
+        if shares <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+        if shares > balance {
+            return Err(Error::InsufficientBalance);
+        }
+
+        let total_shares = Self::load_total_shares(&e);
+        let nav = Self::pool_nav(&e)?;
+        let amount = (shares * nav / total_shares).max(0);
+        if amount > Self::load_pool_balance(&e) {
+            return Err(Error::InsufficientLiquidity);
+        }
+
+        e.storage().instance().set(&DataKey::TotalShares, &(total_shares - shares));
+        if balance == shares {
+            e.storage().persistent().remove(&shares_key);
+        } else {
+            storage::write_persistent(&e, &shares_key, &(balance - shares));
+        }
+        Self::settle_with_pool(&e, -amount);
+        if amount > 0 {
+            Self::transfer_from_contract(&e, &provider, amount)?;
+        }
+        events::liquidity_withdrawn(&e, &provider, amount, shares);
+
+        Ok(amount)
+    }
+
+    // Get the LP vault's net asset value at current oracle prices
+    pub fn get_pool_nav(e: Env) -> Result<i128, Error> {
+        Self::pool_nav(&e)
+    }
+
+    // Get the LP vault's realized balance and total share supply
+    pub fn get_pool_state(e: Env) -> Result<(i128, i128), Error> {
+        Ok((Self::load_pool_balance(&e), Self::load_total_shares(&e)))
+    }
 
-        (trader, margin, leverage, size, side, entry_price, timestamp, is_active)
+    // Get the LP shares held by a provider
+    pub fn get_lp_shares(e: Env, provider: Address) -> Result<i128, Error> {
+        Ok(storage::read_persistent(&e, &DataKey::LpShares(provider)).unwrap_or(0))
+    }
+
+    // Set the shares of fees credited to the LP vault and the insurance fund (admin only)
+    pub fn set_fee_shares(
+        e: Env,
+        admin: Address,
+        lp_fee_share_bps: u32,
+        insurance_fee_share_bps: u32
+    ) -> Result<(), Error> {
+        storage::extend_instance(&e);
+        Self::check_admin(&e, &admin)?;
+
+        if lp_fee_share_bps as i128 + insurance_fee_share_bps as i128 > BPS_DENOMINATOR {
+            return Err(Error::InvalidConfig);
+        }
+
+        let mut config = Self::load_config(&e)?;
+        config.lp_fee_share_bps = lp_fee_share_bps;
+        config.insurance_fee_share_bps = insurance_fee_share_bps;
+        e.storage().instance().set(&DataKey::Config, &config);
+        events::config_updated(&e, &admin, &config);
+        Ok(())
+    }
+
+    // Set the flat fee escrowed with new orders for executing keepers (admin only)
+    pub fn set_keeper_fee(e: Env, admin: Address, keeper_fee: i128) -> Result<(), Error> {
+        storage::extend_instance(&e);
+        Self::check_admin(&e, &admin)?;
+
+        if keeper_fee < 0 {
+            return Err(Error::InvalidConfig);
+        }
+
+        let mut config = Self::load_config(&e)?;
+        config.keeper_fee = keeper_fee;
+        e.storage().instance().set(&DataKey::Config, &config);
+        events::config_updated(&e, &admin, &config);
+        Ok(())
+    }
+
+    // PnL of a position at the given price
+    fn unrealized_pnl(position: &Position, current_price: i128) -> i128 {
+        let price_diff = current_price - position.entry_price;
+        if position.is_long {
+            (price_diff * position.size) / position.entry_price
+        } else {
+            (-price_diff * position.size) / position.entry_price
+        }
+    }
+
+    // Margin a position of the given size needs at the market's maximum leverage
+    fn initial_margin(size: i128, market: &Market) -> i128 {
+        size / market.max_leverage as i128
+    }
+
+    // Minimum equity a position must keep to avoid liquidation
+    fn maintenance_margin(position: &Position, market: &Market) -> i128 {
+        position.size * market.maintenance_margin_bps as i128 / BPS_DENOMINATOR
+    }
+
+    // Withdraw collected protocol fees (admin or treasurer); trader collateral is never withdrawable
+    pub fn withdraw_contract_balance(e: Env, caller: Address, amount: i128) -> Result<bool, Error> {
+        storage::extend_instance(&e);
+        Self::check_role(&e, &caller, Role::Treasurer)?;
+
+        if Self::load_pause_mode(&e) == PauseMode::Halted {
+            return Err(Error::ContractPaused);
+        }
+
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        // Only fees recorded in the ledger can leave the contract
+        let fees = Self::load_protocol_fees(&e);
+        if fees < amount {
+            return Err(Error::InsufficientBalance);
+        }
+        e.storage().instance().set(&DataKey::ProtocolFees, &(fees - amount));
+
+        Self::transfer_from_contract(&e, &caller, amount)?;
+        events::balance_withdrawn(&e, &caller, amount);
+
+        Ok(true)
+    }
+
+    // Get contract balance, as held by the collateral token
+    pub fn get_contract_balance(e: Env) -> Result<i128, Error> {
+        Ok(Self::collateral_client(&e)?.balance(&e.current_contract_address()))
+    }
+
+    // Get position details by ID
+    pub fn get_current_position(e: Env, position_id: u64) -> Result<Position, Error> {
+        Self::load_position(&e, position_id)
+    }
+
+    // Load a position record or fail if the ID is unknown
+    fn load_position(e: &Env, position_id: u64) -> Result<Position, Error> {
+        storage::read_persistent(e, &DataKey::Position(position_id))
+            .ok_or(Error::PositionNotFound)
     }
 
     // Get trader positions
-    pub fn get_trader_positions(e: Env, trader: Address) -> Vec<u64> {
-        let trader_positions_key = Symbol::new(&e, "trader_positions");
-        let trader_positions: Map<Address, Vec<u64>> = e.storage().instance()
-            .get(&trader_positions_key)
-            .unwrap_or(Map::new(&e));
-        
-        trader_positions.get(trader).unwrap_or(Vec::new(&e))
+    pub fn get_trader_positions(e: Env, trader: Address) -> Result<Vec<u64>, Error> {
+        Ok(storage::read_persistent(&e, &DataKey::TraderPositions(trader))
+            .unwrap_or(Vec::new(&e)))
+    }
+
+    // Keep a position and the records it depends on from being archived; callable by keepers
+    pub fn extend_ttl(e: Env, position_id: u64) -> Result<(), Error> {
+        storage::extend_instance(&e);
+
+        let position = Self::load_position(&e, position_id)?;
+        storage::extend_persistent(&e, &DataKey::Position(position_id));
+        storage::extend_persistent(&e, &DataKey::TraderPositions(position.trader.clone()));
+        storage::extend_persistent(&e, &DataKey::TraderNotional(position.trader, position.market.clone()));
+        storage::extend_persistent(&e, &DataKey::Market(position.market.clone()));
+        storage::extend_persistent(&e, &DataKey::MarketState(position.market.clone()));
+        storage::extend_persistent(&e, &DataKey::MarketPaused(position.market));
+        Ok(())
+    }
+
+    // Keep a resting order and the records it depends on from being archived; callable by keepers
+    pub fn extend_order_ttl(e: Env, order_id: u64) -> Result<(), Error> {
+        storage::extend_instance(&e);
+
+        let order = Self::load_order(&e, order_id)?;
+        storage::extend_persistent(&e, &DataKey::Order(order_id));
+        storage::extend_persistent(&e, &DataKey::TraderOrders(order.trader));
+        storage::extend_persistent(&e, &DataKey::Market(order.market.clone()));
+        storage::extend_persistent(&e, &DataKey::MarketState(order.market.clone()));
+        storage::extend_persistent(&e, &DataKey::MarketPaused(order.market));
+        Ok(())
     }
 
     // Get contract stats
-    pub fn get_contract_stats(e: Env) -> (i128, u64, bool) {
-        let total_balance: i128 = e.storage().instance()
-            .get(&Symbol::new(&e, "total_xlm_balance"))
-            .unwrap_or(0);
+    pub fn get_contract_stats(e: Env) -> Result<(i128, u64, PauseMode), Error> {
+        let total_balance = Self::get_contract_balance(e.clone())?;
         let next_position_id: u64 = e.storage().instance()
-            .get(&Symbol::new(&e, "next_position_id"))
+            .get(&DataKey::NextPositionId)
             .unwrap_or(0);
-        let is_active: bool = e.storage().instance()
-            .get(&Symbol::new(&e, "is_active"))
-            .unwrap_or(false);
+        let pause_mode = Self::load_pause_mode(&e);
         
-        (total_balance, next_position_id, is_active)
+        Ok((total_balance, next_position_id, pause_mode))
     }
 
-    // Mock price function (will be replaced with real oracle integration)
-    fn get_mock_price() -> i128 {
-        10_000_000i128 // $1.00 XLM in stroops
+    // Fetch a fresh price for a market's oracle asset from the price oracle contract
+    fn get_oracle_price(e: &Env, asset: &oracle::Asset) -> Result<i128, Error> {
+        let config = Self::load_config(e)?;
+
+        // A failing oracle call is reported as a missing price rather than aborting
+        let price_data = match oracle::PriceOracleClient::new(e, &config.price_oracle).try_get_price(asset) {
+            Ok(Ok(Some(price_data))) => price_data,
+            _ => return Err(Error::PriceUnavailable),
+        };
+
+        if price_data.price <= 0 {
+            return Err(Error::PriceUnavailable);
+        }
+        if e.ledger().timestamp() > price_data.timestamp + config.max_price_age {
+            return Err(Error::PriceStale);
+        }
+
+        Ok(price_data.price)
     }
 
-    // Admin functions
-    pub fn pause_contract(e: Env, admin: Address) {
-        let stored_admin: Address = e.storage().instance()
-            .get(&Symbol::new(&e, "admin"))
-            .expect("Contract not initialized");
-        
-        if stored_admin != admin {
-            panic!("Unauthorized");
+    // Fetch the price the oracle recorded for a given timestamp
+    fn get_oracle_price_at(e: &Env, asset: &oracle::Asset, timestamp: u64) -> Result<i128, Error> {
+        let config = Self::load_config(e)?;
+
+        match oracle::PriceOracleClient::new(e, &config.price_oracle).try_get_price_at(asset, &timestamp) {
+            Ok(Ok(Some(price_data))) if price_data.price > 0 && price_data.timestamp >= timestamp => {
+                Ok(price_data.price)
+            }
+            _ => Err(Error::PriceUnavailable),
         }
+    }
+
+    // Timestamp of the first oracle update strictly after `timestamp`
+    fn next_price_time(e: &Env, timestamp: u64) -> Result<u64, Error> {
+        let config = Self::load_config(e)?;
 
-        e.storage().instance().set(&Symbol::new(&e, "is_active"), &false);
+        let resolution = match oracle::PriceOracleClient::new(e, &config.price_oracle).try_get_oracle_info() {
+            Ok(Ok((_, resolution, _))) if resolution > 0 => resolution as u64,
+            _ => return Err(Error::PriceUnavailable),
+        };
+        Ok((timestamp / resolution + 1) * resolution)
     }
 
-    pub fn resume_contract(e: Env, admin: Address) {
-        let stored_admin: Address = e.storage().instance()
-            .get(&Symbol::new(&e, "admin"))
-            .expect("Contract not initialized");
+    // Verify the caller is the stored admin and has signed the invocation
+    fn check_admin(e: &Env, admin: &Address) -> Result<(), Error> {
+        let config = Self::load_config(e)?;
         
-        if stored_admin != admin {
-            panic!("Unauthorized");
+        if config.admin != *admin {
+            return Err(Error::Unauthorized);
+        }
+        admin.require_auth();
+        Ok(())
+    }
+
+    // Verify the caller is the admin or holds the role, and has signed the invocation
+    fn check_role(e: &Env, caller: &Address, role: Role) -> Result<(), Error> {
+        let config = Self::load_config(e)?;
+
+        if config.admin != *caller {
+            let role_key = DataKey::Role(role, caller.clone());
+            if !e.storage().persistent().has(&role_key) {
+                return Err(Error::Unauthorized);
+            }
+            // Each use keeps the grant from being archived
+            storage::extend_persistent(e, &role_key);
+        }
+        caller.require_auth();
+        Ok(())
+    }
+
+    // Admin functions
+    // Halt all trading (admin or pauser)
+    pub fn pause_contract(e: Env, caller: Address) -> Result<(), Error> {
+        storage::extend_instance(&e);
+        Self::check_role(&e, &caller, Role::Pauser)?;
+        e.storage().instance().set(&DataKey::PauseMode, &PauseMode::Halted);
+        events::contract_paused(&e, &caller);
+        Ok(())
+    }
+
+    // Resume trading (admin only)
+    pub fn resume_contract(e: Env, admin: Address) -> Result<(), Error> {
+        storage::extend_instance(&e);
+        Self::check_admin(&e, &admin)?;
+        e.storage().instance().set(&DataKey::PauseMode, &PauseMode::Active);
+        events::contract_resumed(&e, &admin);
+        Ok(())
+    }
+
+    // Switch between active, close-only and halted trading
+    // Pausers may restrict trading; only the admin can reactivate it
+    pub fn set_pause_mode(e: Env, caller: Address, mode: PauseMode) -> Result<(), Error> {
+        storage::extend_instance(&e);
+        if mode == PauseMode::Active {
+            Self::check_admin(&e, &caller)?;
+        } else {
+            Self::check_role(&e, &caller, Role::Pauser)?;
+        }
+        e.storage().instance().set(&DataKey::PauseMode, &mode);
+        events::pause_mode_changed(&e, &caller, mode);
+        Ok(())
+    }
+
+    // Halt trading on a single market (admin or pauser)
+    pub fn pause_market(e: Env, caller: Address, market: Symbol) -> Result<(), Error> {
+        storage::extend_instance(&e);
+        Self::check_role(&e, &caller, Role::Pauser)?;
+        Self::load_market(&e, &market)?;
+        storage::write_persistent(&e, &DataKey::MarketPaused(market.clone()), &true);
+        events::market_paused(&e, &caller, &market, true);
+        Ok(())
+    }
+
+    // Resume trading on a single market (admin only)
+    pub fn resume_market(e: Env, admin: Address, market: Symbol) -> Result<(), Error> {
+        storage::extend_instance(&e);
+        Self::check_admin(&e, &admin)?;
+        Self::load_market(&e, &market)?;
+        e.storage().persistent().remove(&DataKey::MarketPaused(market.clone()));
+        events::market_paused(&e, &admin, &market, false);
+        Ok(())
+    }
+
+    // Check whether a single market is paused
+    pub fn is_market_paused(e: Env, market: Symbol) -> Result<bool, Error> {
+        Ok(e.storage().persistent().has(&DataKey::MarketPaused(market)))
+    }
+
+    // Current contract-wide trading mode
+    fn load_pause_mode(e: &Env) -> PauseMode {
+        e.storage().instance()
+            .get(&DataKey::PauseMode)
+            .unwrap_or(PauseMode::Active)
+    }
+
+    // Reject trading actions blocked by the contract or market pause state
+    fn check_trading_allowed(e: &Env, market: &Symbol, adds_exposure: bool) -> Result<(), Error> {
+        match Self::load_pause_mode(e) {
+            PauseMode::Halted => return Err(Error::ContractPaused),
+            PauseMode::CloseOnly if adds_exposure => return Err(Error::CloseOnly),
+            _ => {}
+        }
+        if e.storage().persistent().has(&DataKey::MarketPaused(market.clone())) {
+            return Err(Error::MarketPaused);
         }
+        Ok(())
+    }
+
+    // Add or update a market (admin or risk manager)
+    pub fn set_market(e: Env, caller: Address, market: Market) -> Result<(), Error> {
+        storage::extend_instance(&e);
+        Self::check_role(&e, &caller, Role::RiskManager)?;
+
+        if market.max_leverage < 1 {
+            return Err(Error::InvalidMarketConfig);
+        }
+        if market.open_fee_bps as i128 > BPS_DENOMINATOR
+            || market.close_fee_bps as i128 > BPS_DENOMINATOR
+            || market.liquidation_fee_bps as i128 > BPS_DENOMINATOR
+            || market.keeper_reward_bps as i128 > BPS_DENOMINATOR
+            || market.funding_rate_bps as i128 > BPS_DENOMINATOR
+        {
+            return Err(Error::InvalidMarketConfig);
+        }
+        if market.max_open_interest < 0 || market.max_side_open_interest < 0 || market.max_trader_notional < 0 {
+            return Err(Error::InvalidMarketConfig);
+        }
+        // Positions at max leverage must start above the maintenance margin
+        if market.maintenance_margin_bps as i128 * market.max_leverage as i128 >= BPS_DENOMINATOR {
+            return Err(Error::InvalidMarketConfig);
+        }
+
+        let key = DataKey::Market(market.id.clone());
+        if !e.storage().persistent().has(&key) {
+            let mut market_list: Vec<Symbol> = e.storage().instance()
+                .get(&DataKey::MarketList)
+                .unwrap_or(Vec::new(&e));
+            market_list.push_back(market.id.clone());
+            e.storage().instance().set(&DataKey::MarketList, &market_list);
+        }
+        storage::write_persistent(&e, &key, &market);
+        events::market_updated(&e, &caller, &market);
+        Ok(())
+    }
+
+    // Get market configuration
+    pub fn get_market(e: Env, market: Symbol) -> Result<Market, Error> {
+        Self::load_market(&e, &market)
+    }
+
+    // Get all registered market IDs
+    pub fn get_markets(e: Env) -> Result<Vec<Symbol>, Error> {
+        Ok(e.storage().instance()
+            .get(&DataKey::MarketList)
+            .unwrap_or(Vec::new(&e)))
+    }
 
-        e.storage().instance().set(&Symbol::new(&e, "is_active"), &true);
+    // Load a market or fail if it is not registered
+    fn load_market(e: &Env, market: &Symbol) -> Result<Market, Error> {
+        storage::read_persistent(e, &DataKey::Market(market.clone()))
+            .ok_or(Error::MarketNotFound)
     }
 }
\ No newline at end of file
@@ -0,0 +1,32 @@
+// Interface of the PriceOracleContract price feed consumed by the trading contract
+// Asset and PriceData mirror the Reflector definitions used by the oracle contract
+
+use soroban_sdk::{contracttype, Address, Env, Symbol};
+
+// Oracle contract interface exported as PriceOracleClient
+#[soroban_sdk::contractclient(name = "PriceOracleClient")]
+#[allow(dead_code)]
+pub trait PriceOracle {
+    // Quotes the most recent price for an asset
+    fn get_price(e: Env, asset: Asset) -> Option<PriceData>;
+    // Quotes the price recorded for an asset at a specific timestamp
+    fn get_price_at(e: Env, asset: Asset, timestamp: u64) -> Option<PriceData>;
+    // Price decimals, update resolution in seconds and the latest update timestamp
+    fn get_oracle_info(e: Env) -> (u32, u32, u64);
+}
+
+// Quoted asset definition
+#[contracttype(export = false)]
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Asset {
+    Stellar(Address), // for Stellar Classic and Soroban assets
+    Other(Symbol)     // for any external currencies/tokens/assets/symbols
+}
+
+// Price record definition
+#[contracttype(export = false)]
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct PriceData {
+    pub price: i128,   // asset price at given point in time
+    pub timestamp: u64 // record timestamp
+}
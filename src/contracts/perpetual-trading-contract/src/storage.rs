@@ -0,0 +1,73 @@
+// Storage layout and TTL management
+//
+// Contract-wide configuration and small counters live in instance storage, which
+// shares the contract's TTL. Positions, per-trader indexes and markets live in
+// persistent storage and are bumped whenever they are written or touched by a keeper.
+
+use soroban_sdk::{contracttype, Address, Env, IntoVal, Symbol, TryFromVal, Val};
+
+use crate::Role;
+
+pub(crate) const DAY_IN_LEDGERS: u32 = 17280;
+
+pub(crate) const INSTANCE_BUMP_AMOUNT: u32 = 7 * DAY_IN_LEDGERS;
+pub(crate) const INSTANCE_LIFETIME_THRESHOLD: u32 = INSTANCE_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
+pub(crate) const PERSISTENT_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
+pub(crate) const PERSISTENT_LIFETIME_THRESHOLD: u32 = PERSISTENT_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DataKey {
+    // Instance storage
+    Initialized,
+    Config,
+    PendingAdmin,
+    NextPositionId,
+    NextOrderId,
+    PauseMode,
+    MarketList,
+    BadDebt,
+    ProtocolFees,
+    PoolBalance,
+    TotalShares,
+    InsuranceBalance,
+    InsuranceCoverageCount,
+    // Persistent storage
+    Position(u64),
+    TraderPositions(Address),
+    Market(Symbol),
+    MarketState(Symbol),
+    MarketPaused(Symbol),
+    Role(Role, Address),
+    LpShares(Address),
+    InsuranceCoverage(u64),
+    Order(u64),
+    TraderOrders(Address),
+    TraderNotional(Address, Symbol),
+}
+
+pub(crate) fn extend_instance(e: &Env) {
+    e.storage()
+        .instance()
+        .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+}
+
+pub(crate) fn extend_persistent(e: &Env, key: &DataKey) {
+    if e.storage().persistent().has(key) {
+        e.storage()
+            .persistent()
+            .extend_ttl(key, PERSISTENT_LIFETIME_THRESHOLD, PERSISTENT_BUMP_AMOUNT);
+    }
+}
+
+pub(crate) fn read_persistent<V: TryFromVal<Env, Val>>(e: &Env, key: &DataKey) -> Option<V> {
+    e.storage().persistent().get(key)
+}
+
+pub(crate) fn write_persistent<V: IntoVal<Env, Val>>(e: &Env, key: &DataKey, value: &V) {
+    e.storage().persistent().set(key, value);
+    e.storage()
+        .persistent()
+        .extend_ttl(key, PERSISTENT_LIFETIME_THRESHOLD, PERSISTENT_BUMP_AMOUNT);
+}
@@ -0,0 +1,1049 @@
+#![cfg(test)]
+
+use soroban_sdk::testutils::storage::Persistent as _;
+use soroban_sdk::testutils::{Address as _, Events, Ledger, MockAuth, MockAuthInvoke};
+use soroban_sdk::{
+    contract, contractimpl, symbol_short, token, Address, BytesN, Env, IntoVal, Symbol, TryFromVal, Val, Vec,
+};
+
+use crate::oracle::{Asset, PriceData};
+use crate::{
+    DataKey, Error, Market, OrderKind, OrderStatus, PauseMode, PerpetualTradingContract, PerpetualTradingContractClient,
+    PositionStatus, Role,
+};
+use crate::storage::{DAY_IN_LEDGERS, PERSISTENT_BUMP_AMOUNT, PERSISTENT_LIFETIME_THRESHOLD};
+
+// Price feed stand-in exposing the PriceOracleContract interface
+#[contract]
+pub struct MockPriceOracle;
+
+#[contractimpl]
+impl MockPriceOracle {
+    pub fn set_price(e: Env, price: i128, timestamp: u64) {
+        let price_data = PriceData { price, timestamp };
+        e.storage().instance().set(&symbol_short!("price"), &price_data);
+        e.storage().instance().set(&timestamp, &price_data);
+    }
+
+    pub fn get_price(e: Env, _asset: Asset) -> Option<PriceData> {
+        e.storage().instance().get(&symbol_short!("price"))
+    }
+
+    pub fn get_price_at(e: Env, _asset: Asset, timestamp: u64) -> Option<PriceData> {
+        e.storage().instance().get(&timestamp)
+    }
+
+    pub fn get_oracle_info(e: Env) -> (u32, u32, u64) {
+        let last: Option<PriceData> = e.storage().instance().get(&symbol_short!("price"));
+        (14, 300, last.map(|p| p.timestamp).unwrap_or(0))
+    }
+}
+
+struct Setup<'a> {
+    e: Env,
+    contract: PerpetualTradingContractClient<'a>,
+    admin: Address,
+    trader: Address,
+    market: Symbol,
+}
+
+const PRICE: i128 = 10_000_000;
+const MARGIN: i128 = 100_000_000;
+
+// Slippage bounds that accept any execution price
+const NO_MAX: i128 = i128::MAX;
+const NO_MIN: i128 = 0;
+
+fn setup<'a>() -> Setup<'a> {
+    let e = Env::default();
+    e.mock_all_auths();
+    e.ledger().with_mut(|l| l.timestamp = 1_000);
+
+    let admin = Address::generate(&e);
+    let trader = Address::generate(&e);
+
+    let token_id = e.register_stellar_asset_contract_v2(admin.clone()).address();
+    token::StellarAssetClient::new(&e, &token_id).mint(&trader, &(10 * MARGIN));
+
+    let oracle_id = e.register_contract(None, MockPriceOracle);
+    MockPriceOracleClient::new(&e, &oracle_id).set_price(&PRICE, &1_000);
+
+    let contract_id = e.register_contract(None, PerpetualTradingContract);
+    let contract = PerpetualTradingContractClient::new(&e, &contract_id);
+    contract.initialize(&admin, &oracle_id, &token_id);
+
+    let market = Symbol::new(&e, "XLM");
+    contract.set_market(
+        &admin,
+        &Market {
+            id: market.clone(),
+            asset: Asset::Stellar(token_id),
+            max_leverage: 10,
+            open_fee_bps: 10,
+            close_fee_bps: 10,
+            maintenance_margin_bps: 500,
+            liquidation_fee_bps: 100,
+            keeper_reward_bps: 5_000,
+            funding_rate_bps: 10,
+            max_open_interest: 0,
+            max_side_open_interest: 0,
+            max_trader_notional: 0,
+            enabled: true,
+        },
+    );
+
+    Setup { e, contract, admin, trader, market }
+}
+
+// Topics and payload of the last event of the given type published by the contract
+fn last_event(s: &Setup, event_type: Symbol) -> (Vec<Val>, Val) {
+    let mut found = None;
+    for (contract, topics, data) in s.e.events().all().iter() {
+        let matches = Symbol::try_from_val(&s.e, &topics.get(0).unwrap()).map(|t| t == event_type);
+        if contract == s.contract.address && matches.unwrap_or(false) {
+            found = Some((topics, data));
+        }
+    }
+    found.expect("event not published")
+}
+
+#[test]
+fn admin_signed_pause_succeeds() {
+    let s = setup();
+
+    s.contract
+        .mock_auths(&[MockAuth {
+            address: &s.admin,
+            invoke: &MockAuthInvoke {
+                contract: &s.contract.address,
+                fn_name: "pause_contract",
+                args: (s.admin.clone(),).into_val(&s.e),
+                sub_invokes: &[],
+            },
+        }])
+        .pause_contract(&s.admin);
+
+    let (_, _, mode) = s.contract.get_contract_stats();
+    assert_eq!(mode, PauseMode::Halted);
+}
+
+#[test]
+fn pause_without_admin_auth_fails() {
+    let s = setup();
+    s.e.mock_auths(&[]);
+
+    assert!(s.contract.try_pause_contract(&s.admin).is_err());
+    assert!(s.contract.try_resume_contract(&s.admin).is_err());
+}
+
+#[test]
+fn withdraw_signed_by_another_address_fails() {
+    let s = setup();
+    s.contract.open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+
+    let attacker = Address::generate(&s.e);
+    let result = s
+        .contract
+        .mock_auths(&[MockAuth {
+            address: &attacker,
+            invoke: &MockAuthInvoke {
+                contract: &s.contract.address,
+                fn_name: "withdraw_contract_balance",
+                args: (s.admin.clone(), 1_000i128).into_val(&s.e),
+                sub_invokes: &[],
+            },
+        }])
+        .try_withdraw_contract_balance(&s.admin, &1_000);
+    assert!(result.is_err());
+}
+
+#[test]
+fn withdraw_by_non_admin_is_unauthorized() {
+    let s = setup();
+
+    let result = s.contract.try_withdraw_contract_balance(&s.trader, &1_000);
+    assert_eq!(result, Err(Ok(Error::Unauthorized)));
+}
+
+#[test]
+fn set_market_without_admin_auth_fails() {
+    let s = setup();
+    let market = s.contract.get_market(&s.market);
+    s.e.mock_auths(&[]);
+
+    assert!(s.contract.try_set_market(&s.admin, &market).is_err());
+}
+
+#[test]
+fn open_position_without_trader_auth_fails() {
+    let s = setup();
+    s.e.mock_auths(&[]);
+
+    let result = s.contract.try_open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+    assert!(result.is_err());
+    assert_eq!(s.contract.get_trader_positions(&s.trader).len(), 0);
+}
+
+#[test]
+fn close_position_without_trader_auth_fails() {
+    let s = setup();
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+    s.e.mock_auths(&[]);
+
+    assert!(s.contract.try_close_position(&s.trader, &position_id, &NO_MIN).is_err());
+}
+
+#[test]
+fn initialize_twice_fails() {
+    let s = setup();
+    let config = s.contract.get_config();
+
+    let attacker = Address::generate(&s.e);
+    let result = s.contract.try_initialize(&attacker, &config.price_oracle, &config.collateral_token);
+    assert_eq!(result, Err(Ok(Error::AlreadyInitialized)));
+    assert_eq!(s.contract.get_config().admin, s.admin);
+}
+
+#[test]
+fn admin_transfer_requires_acceptance() {
+    let s = setup();
+    let new_admin = Address::generate(&s.e);
+
+    s.contract.propose_admin(&s.admin, &new_admin);
+    assert_eq!(s.contract.get_config().admin, s.admin);
+    assert_eq!(s.contract.get_pending_admin(), Some(new_admin.clone()));
+
+    let other = Address::generate(&s.e);
+    assert_eq!(s.contract.try_accept_admin(&other), Err(Ok(Error::Unauthorized)));
+
+    s.contract.accept_admin(&new_admin);
+    assert_eq!(s.contract.get_config().admin, new_admin);
+    assert_eq!(s.contract.get_pending_admin(), None);
+    assert_eq!(s.contract.try_pause_contract(&s.admin), Err(Ok(Error::Unauthorized)));
+}
+
+#[test]
+fn accept_admin_without_nominee_auth_fails() {
+    let s = setup();
+    let new_admin = Address::generate(&s.e);
+    s.contract.propose_admin(&s.admin, &new_admin);
+    s.e.mock_auths(&[]);
+
+    assert!(s.contract.try_accept_admin(&new_admin).is_err());
+}
+
+#[test]
+fn pauser_can_pause_but_not_resume() {
+    let s = setup();
+    let pauser = Address::generate(&s.e);
+    s.contract.grant_role(&s.admin, &Role::Pauser, &pauser);
+
+    s.contract.pause_contract(&pauser);
+    assert_eq!(s.contract.try_resume_contract(&pauser), Err(Ok(Error::Unauthorized)));
+    assert_eq!(s.contract.try_set_pause_mode(&pauser, &PauseMode::Active), Err(Ok(Error::Unauthorized)));
+    s.contract.set_pause_mode(&pauser, &PauseMode::CloseOnly);
+    s.contract.pause_market(&pauser, &s.market);
+    assert_eq!(s.contract.try_resume_market(&pauser, &s.market), Err(Ok(Error::Unauthorized)));
+
+    let market = s.contract.get_market(&s.market);
+    assert_eq!(s.contract.try_set_market(&pauser, &market), Err(Ok(Error::Unauthorized)));
+    assert_eq!(s.contract.try_withdraw_contract_balance(&pauser, &1), Err(Ok(Error::Unauthorized)));
+
+    // Using the role extends the grant
+    let role_key = DataKey::Role(Role::Pauser, pauser.clone());
+    let ttl = || s.e.as_contract(&s.contract.address, || s.e.storage().persistent().get_ttl(&role_key));
+    s.e.ledger().with_mut(|l| l.sequence_number += 2 * DAY_IN_LEDGERS);
+    assert!(ttl() < PERSISTENT_LIFETIME_THRESHOLD);
+    s.contract.pause_contract(&pauser);
+    assert_eq!(ttl(), PERSISTENT_BUMP_AMOUNT);
+}
+
+#[test]
+fn risk_manager_and_treasurer_roles() {
+    let s = setup();
+    let risk_manager = Address::generate(&s.e);
+    let treasurer = Address::generate(&s.e);
+    s.contract.grant_role(&s.admin, &Role::RiskManager, &risk_manager);
+    s.contract.grant_role(&s.admin, &Role::Treasurer, &treasurer);
+
+    let mut market = s.contract.get_market(&s.market);
+    market.max_leverage = 5;
+    s.contract.set_market(&risk_manager, &market);
+    assert_eq!(s.contract.get_market(&s.market).max_leverage, 5);
+    assert_eq!(s.contract.try_pause_contract(&risk_manager), Err(Ok(Error::Unauthorized)));
+
+    s.contract.open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+    s.contract.withdraw_contract_balance(&treasurer, &1_000);
+    assert_eq!(token::Client::new(&s.e, &s.contract.get_config().collateral_token).balance(&treasurer), 1_000);
+
+    s.contract.revoke_role(&s.admin, &Role::Treasurer, &treasurer);
+    assert!(!s.contract.has_role(&Role::Treasurer, &treasurer));
+    assert_eq!(s.contract.try_withdraw_contract_balance(&treasurer, &1_000), Err(Ok(Error::Unauthorized)));
+}
+
+#[test]
+fn grant_role_by_non_admin_is_unauthorized() {
+    let s = setup();
+    let pauser = Address::generate(&s.e);
+    s.contract.grant_role(&s.admin, &Role::Pauser, &pauser);
+
+    let result = s.contract.try_grant_role(&pauser, &Role::Treasurer, &pauser);
+    assert_eq!(result, Err(Ok(Error::Unauthorized)));
+}
+
+#[test]
+fn upgrade_by_non_admin_is_unauthorized() {
+    let s = setup();
+
+    let result = s.contract.try_upgrade(&s.trader, &BytesN::from_array(&s.e, &[0; 32]));
+    assert_eq!(result, Err(Ok(Error::Unauthorized)));
+}
+
+#[test]
+fn migrate_brings_config_up_to_the_current_version() {
+    let s = setup();
+    let set_version = |version: u32| {
+        s.e.as_contract(&s.contract.address, || {
+            let mut config: crate::Config = s.e.storage().instance().get(&DataKey::Config).unwrap();
+            config.version = version;
+            s.e.storage().instance().set(&DataKey::Config, &config);
+        });
+    };
+
+    set_version(1);
+    assert_eq!(s.contract.migrate(&s.admin), s.contract.version());
+    assert_eq!(s.contract.get_config().version, s.contract.version());
+
+    // Storage written by newer code is left alone
+    set_version(s.contract.version() + 1);
+    assert_eq!(s.contract.try_migrate(&s.admin), Err(Ok(Error::InvalidVersion)));
+
+    let other = Address::generate(&s.e);
+    assert_eq!(s.contract.try_migrate(&other), Err(Ok(Error::Unauthorized)));
+}
+
+#[test]
+fn withdrawals_are_limited_to_collected_fees() {
+    let s = setup();
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+
+    // 10 bps of the 2x notional on open, again on close at an unchanged price;
+    // the LP vault keeps its share and the rest is booked as protocol fees
+    let fee = 2 * MARGIN * 10 / 10_000;
+    let config = s.contract.get_config();
+    let protocol_fee = fee
+        - fee * config.lp_fee_share_bps as i128 / 10_000
+        - fee * config.insurance_fee_share_bps as i128 / 10_000;
+    assert_eq!(s.contract.get_protocol_fees(), protocol_fee);
+    assert_eq!(s.contract.get_current_position(&position_id).margin, MARGIN - fee);
+
+    let result = s.contract.try_withdraw_contract_balance(&s.admin, &(protocol_fee + 1));
+    assert_eq!(result, Err(Ok(Error::InsufficientBalance)));
+
+    s.contract.close_position(&s.trader, &position_id, &NO_MIN);
+    assert_eq!(s.contract.get_protocol_fees(), 2 * protocol_fee);
+
+    s.contract.withdraw_contract_balance(&s.admin, &(2 * protocol_fee));
+    assert_eq!(s.contract.get_protocol_fees(), 0);
+    let (pool_balance, _) = s.contract.get_pool_state();
+    assert_eq!(s.contract.get_contract_balance(), pool_balance + s.contract.get_insurance_balance());
+}
+
+#[test]
+fn lp_shares_track_pool_nav() {
+    let s = setup();
+    let oracle = MockPriceOracleClient::new(&s.e, &s.contract.get_config().price_oracle);
+    let provider = Address::generate(&s.e);
+    token::StellarAssetClient::new(&s.e, &s.contract.get_config().collateral_token).mint(&provider, &(20 * MARGIN));
+
+    assert_eq!(s.contract.deposit_liquidity(&provider, &(10 * MARGIN)), 10 * MARGIN);
+    assert_eq!(s.contract.get_pool_nav(), 10 * MARGIN);
+
+    // A long gaining 10% at 2x notional is a liability of the pool
+    s.contract.set_fee_shares(&s.admin, &0, &0);
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+    oracle.set_price(&(PRICE * 11 / 10), &1_000);
+    assert_eq!(s.contract.get_pool_nav(), 10 * MARGIN - 2 * MARGIN / 10);
+
+    // New shares are priced at the lower NAV
+    let second = Address::generate(&s.e);
+    token::StellarAssetClient::new(&s.e, &s.contract.get_config().collateral_token).mint(&second, &(98 * MARGIN / 10));
+    assert_eq!(s.contract.deposit_liquidity(&second, &(98 * MARGIN / 10)), 10 * MARGIN);
+
+    // Realizing the trade moves the PnL from NAV into the pool balance
+    s.contract.close_position(&s.trader, &position_id, &NO_MIN);
+    assert_eq!(s.contract.get_pool_nav(), 196 * MARGIN / 10);
+    assert_eq!(s.contract.get_market_state(&s.market).long_units, 0);
+
+    assert_eq!(s.contract.withdraw_liquidity(&provider, &(10 * MARGIN)), 98 * MARGIN / 10);
+    assert_eq!(s.contract.get_lp_shares(&provider), 0);
+    assert_eq!(s.contract.try_withdraw_liquidity(&provider, &1), Err(Ok(Error::InsufficientBalance)));
+}
+
+#[test]
+fn pool_nav_marks_open_interest_at_14_decimal_prices() {
+    let s = setup();
+    let oracle = MockPriceOracleClient::new(&s.e, &s.contract.get_config().price_oracle);
+    let provider = Address::generate(&s.e);
+    token::StellarAssetClient::new(&s.e, &s.contract.get_config().collateral_token).mint(&provider, &(10 * MARGIN));
+    s.contract.deposit_liquidity(&provider, &(10 * MARGIN));
+    s.contract.set_fee_shares(&s.admin, &0, &0);
+
+    // BTC at 50_000 with the oracle's 14 decimals; opening at the mark leaves NAV unchanged
+    let btc = 5_000_000_000_000_000_000;
+    oracle.set_price(&btc, &1_000);
+    s.contract.open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+    assert_eq!(s.contract.get_pool_nav(), 10 * MARGIN);
+
+    oracle.set_price(&(btc * 11 / 10), &1_000);
+    assert_eq!(s.contract.get_pool_nav(), 10 * MARGIN - 2 * MARGIN / 10);
+}
+
+#[test]
+fn first_deposit_does_not_capture_existing_pool_balance() {
+    let s = setup();
+    let provider = Address::generate(&s.e);
+    token::StellarAssetClient::new(&s.e, &s.contract.get_config().collateral_token).mint(&provider, &MARGIN);
+
+    // Fee shares credited to the pool before anyone has provided liquidity
+    for _ in 0..5 {
+        let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+        s.contract.close_position(&s.trader, &position_id, &NO_MIN);
+    }
+    let (pool_balance, total_shares) = s.contract.get_pool_state();
+    assert!(pool_balance > 0);
+    assert_eq!(total_shares, 0);
+    let insurance = s.contract.get_insurance_balance();
+
+    // The existing balance moves to the insurance fund and the first depositor owns only what they add
+    assert_eq!(s.contract.deposit_liquidity(&provider, &MARGIN), MARGIN);
+    assert_eq!(s.contract.get_pool_state(), (MARGIN, MARGIN));
+    assert_eq!(s.contract.get_insurance_balance(), insurance + pool_balance);
+
+    // Later income accrues to the LPs in full
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+    s.contract.close_position(&s.trader, &position_id, &NO_MIN);
+    let (grown, _) = s.contract.get_pool_state();
+    assert!(grown > MARGIN);
+    assert_eq!(s.contract.withdraw_liquidity(&provider, &MARGIN), grown);
+}
+
+#[test]
+fn profits_are_capped_at_the_pool_balance() {
+    let s = setup();
+    let oracle = MockPriceOracleClient::new(&s.e, &s.contract.get_config().price_oracle);
+    let token = token::Client::new(&s.e, &s.contract.get_config().collateral_token);
+    s.contract.set_fee_shares(&s.admin, &0, &0);
+    s.contract.deposit_liquidity(&s.trader, &(MARGIN / 20));
+
+    // Another trader's escrowed order funds sit in the contract alongside the pool
+    let other = Address::generate(&s.e);
+    let keeper_fee = s.contract.get_config().keeper_fee;
+    token::StellarAssetClient::new(&s.e, &token.address).mint(&other, &(MARGIN + keeper_fee));
+    s.contract.place_limit_order(&other, &s.market, &true, &(2 * MARGIN), &2, &(PRICE / 2), &2_000);
+
+    // A 10% gain at 2x is owed 2 * MARGIN / 10, but the pool only holds MARGIN / 20
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+    oracle.set_price(&(PRICE * 11 / 10), &1_000);
+    assert_eq!(s.contract.close_position(&s.trader, &position_id, &NO_MIN), MARGIN / 20);
+    assert_eq!(s.contract.get_pool_state().0, 0);
+    assert_eq!(token.balance(&s.contract.address), MARGIN + keeper_fee + s.contract.get_protocol_fees());
+}
+
+#[test]
+fn insurance_covers_shortfall_before_bad_debt() {
+    let s = setup();
+    let oracle = MockPriceOracleClient::new(&s.e, &s.contract.get_config().price_oracle);
+    let keeper = Address::generate(&s.e);
+    s.contract.set_fee_shares(&s.admin, &0, &0);
+    s.contract.fund_insurance(&s.trader, &(MARGIN / 2));
+
+    // At 10x a 20% drop loses twice the margin
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &10, &true, &NO_MAX, &None, &None);
+    let margin = s.contract.get_current_position(&position_id).margin;
+    oracle.set_price(&(PRICE * 8 / 10), &1_000);
+    s.contract.liquidate(&keeper, &position_id);
+
+    let shortfall = 2 * MARGIN - margin;
+    assert_eq!(s.contract.get_insurance_balance(), 0);
+    assert_eq!(s.contract.get_bad_debt(), shortfall - MARGIN / 2);
+    assert_eq!(s.contract.get_insurance_coverage_count(), 1);
+
+    let coverage = s.contract.get_insurance_coverage(&0);
+    assert_eq!(coverage.position_id, position_id);
+    assert_eq!(coverage.shortfall, shortfall);
+    assert_eq!(coverage.covered, MARGIN / 2);
+    assert_eq!(s.contract.try_get_insurance_coverage(&1), Err(Ok(Error::CoverageNotFound)));
+}
+
+#[test]
+fn margin_can_be_added_and_removed_down_to_initial_margin() {
+    let s = setup();
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &5, &true, &NO_MAX, &None, &None);
+    let margin = s.contract.get_current_position(&position_id).margin;
+
+    s.contract.add_margin(&s.trader, &position_id, &MARGIN);
+    let position = s.contract.get_current_position(&position_id);
+    assert_eq!(position.margin, margin + MARGIN);
+    assert_eq!(position.leverage, 2);
+
+    // 5x notional needs half the margin at the market's 10x limit
+    let initial_margin = 5 * MARGIN / 10;
+    let excess = margin + MARGIN - initial_margin;
+    let result = s.contract.try_remove_margin(&s.trader, &position_id, &(excess + 1));
+    assert_eq!(result, Err(Ok(Error::MarginTooLow)));
+
+    s.contract.remove_margin(&s.trader, &position_id, &excess);
+    let position = s.contract.get_current_position(&position_id);
+    assert_eq!(position.margin, initial_margin);
+    assert_eq!(position.leverage, 10);
+}
+
+#[test]
+fn increase_position_averages_entry_price() {
+    let s = setup();
+    let oracle = MockPriceOracleClient::new(&s.e, &s.contract.get_config().price_oracle);
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+
+    // Adding the same size at a 25% higher price gives a harmonic mean entry of P / 0.9
+    oracle.set_price(&(PRICE * 5 / 4), &1_000);
+    s.contract.increase_position(&s.trader, &position_id, &MARGIN, &2, &NO_MAX);
+
+    let position = s.contract.get_current_position(&position_id);
+    assert_eq!(position.size, 4 * MARGIN);
+    assert_eq!(position.entry_price, PRICE * 10 / 9);
+
+    let state = s.contract.get_market_state(&s.market);
+    assert_eq!(state.long_open_interest, 4 * MARGIN);
+
+    let other = Address::generate(&s.e);
+    let result = s.contract.try_increase_position(&other, &position_id, &MARGIN, &2, &NO_MAX);
+    assert_eq!(result, Err(Ok(Error::Unauthorized)));
+}
+
+#[test]
+fn increase_position_averages_entry_price_at_14_decimal_prices() {
+    let s = setup();
+    let oracle = MockPriceOracleClient::new(&s.e, &s.contract.get_config().price_oracle);
+
+    // BTC at 60_000 with the oracle's 14 decimals
+    let btc = 6_000_000_000_000_000_000;
+    oracle.set_price(&btc, &1_000);
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+    assert_eq!(s.contract.get_current_position(&position_id).entry_price, btc);
+
+    oracle.set_price(&(btc * 5 / 4), &1_000);
+    s.contract.increase_position(&s.trader, &position_id, &MARGIN, &2, &NO_MAX);
+
+    let position = s.contract.get_current_position(&position_id);
+    assert_eq!(position.size, 4 * MARGIN);
+    assert_eq!(position.entry_price, btc * 10 / 9);
+}
+
+#[test]
+fn reduce_position_realizes_pnl_pro_rata() {
+    let s = setup();
+    let oracle = MockPriceOracleClient::new(&s.e, &s.contract.get_config().price_oracle);
+    let provider = Address::generate(&s.e);
+    token::StellarAssetClient::new(&s.e, &s.contract.get_config().collateral_token).mint(&provider, &(10 * MARGIN));
+    s.contract.deposit_liquidity(&provider, &(10 * MARGIN));
+
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &4, &true, &NO_MAX, &None, &None);
+    let opened = s.contract.get_current_position(&position_id);
+    let fee = 4 * MARGIN * 10 / 10_000;
+    assert_eq!(opened.fees_paid, fee);
+
+    // A 10% gain on 4x notional, realized a quarter at a time
+    oracle.set_price(&(PRICE * 11 / 10), &1_000);
+    let pnl = s.contract.reduce_position(&s.trader, &position_id, &MARGIN, &NO_MIN);
+    assert_eq!(pnl, MARGIN / 10);
+
+    let reduced = s.contract.get_current_position(&position_id);
+    assert_eq!(reduced.status, PositionStatus::Open);
+    assert_eq!(reduced.size, 3 * MARGIN);
+    assert_eq!(reduced.margin, opened.margin * 3 / 4);
+    assert_eq!(reduced.entry_price, PRICE);
+    assert_eq!(reduced.realized_pnl, MARGIN / 10);
+    assert_eq!(reduced.fees_paid, fee + MARGIN * 10 / 10_000);
+    assert_eq!(s.contract.get_market_state(&s.market).long_open_interest, 3 * MARGIN);
+
+    let result = s.contract.try_reduce_position(&s.trader, &position_id, &(3 * MARGIN), &NO_MIN);
+    assert_eq!(result, Err(Ok(Error::InvalidAmount)));
+
+    s.contract.close_position(&s.trader, &position_id, &NO_MIN);
+    let closed = s.contract.get_current_position(&position_id);
+    assert_eq!(closed.status, PositionStatus::Closed);
+    assert_eq!(closed.pnl, 4 * MARGIN / 10);
+    assert_eq!(closed.fees_paid, 2 * fee);
+}
+
+#[test]
+fn closed_position_has_no_liquidation_price() {
+    let s = setup();
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+    s.contract.close_position(&s.trader, &position_id, &NO_MIN);
+
+    // A full close leaves a zero size, so there is no price to solve for
+    assert_eq!(s.contract.get_current_position(&position_id).size, 0);
+    let result = s.contract.try_get_liquidation_price(&position_id);
+    assert_eq!(result, Err(Ok(Error::PositionClosed)));
+}
+
+#[test]
+fn limit_order_executes_once_price_crosses() {
+    let s = setup();
+    let oracle = MockPriceOracleClient::new(&s.e, &s.contract.get_config().price_oracle);
+    let token = token::Client::new(&s.e, &s.contract.get_config().collateral_token);
+    let keeper = Address::generate(&s.e);
+    let keeper_fee = s.contract.get_config().keeper_fee;
+    let balance = token.balance(&s.trader);
+
+    let order_id = s.contract.place_limit_order(&s.trader, &s.market, &true, &(2 * MARGIN), &2, &(PRICE * 9 / 10), &2_000);
+    assert_eq!(token.balance(&s.trader), balance - MARGIN - keeper_fee);
+    assert_eq!(s.contract.get_trader_orders(&s.trader).len(), 1);
+
+    // A long limit fills only at or below its price
+    let result = s.contract.try_execute_order(&keeper, &order_id);
+    assert_eq!(result, Err(Ok(Error::OrderNotTriggered)));
+
+    oracle.set_price(&(PRICE * 9 / 10), &1_000);
+    let position_id = s.contract.execute_order(&keeper, &order_id);
+    assert_eq!(token.balance(&keeper), keeper_fee);
+
+    let order = s.contract.get_order(&order_id);
+    assert_eq!(order.status, OrderStatus::Executed);
+    assert_eq!(order.position_id, position_id);
+    let position = s.contract.get_current_position(&position_id);
+    assert_eq!(position.size, 2 * MARGIN);
+    assert_eq!(position.entry_price, PRICE * 9 / 10);
+
+    let result = s.contract.try_cancel_order(&s.trader, &order_id);
+    assert_eq!(result, Err(Ok(Error::OrderNotOpen)));
+}
+
+#[test]
+fn cancelled_and_expired_orders_do_not_execute() {
+    let s = setup();
+    let token = token::Client::new(&s.e, &s.contract.get_config().collateral_token);
+    let keeper = Address::generate(&s.e);
+    let balance = token.balance(&s.trader);
+
+    let order_id = s.contract.place_limit_order(&s.trader, &s.market, &false, &(2 * MARGIN), &2, &PRICE, &2_000);
+    let other = Address::generate(&s.e);
+    assert_eq!(s.contract.try_cancel_order(&other, &order_id), Err(Ok(Error::Unauthorized)));
+    s.contract.cancel_order(&s.trader, &order_id);
+    assert_eq!(token.balance(&s.trader), balance);
+    assert_eq!(s.contract.try_execute_order(&keeper, &order_id), Err(Ok(Error::OrderNotOpen)));
+
+    let order_id = s.contract.place_limit_order(&s.trader, &s.market, &false, &(2 * MARGIN), &2, &PRICE, &2_000);
+    s.e.ledger().with_mut(|l| l.timestamp = 2_001);
+    assert_eq!(s.contract.try_execute_order(&keeper, &order_id), Err(Ok(Error::OrderExpired)));
+}
+
+#[test]
+fn extend_order_ttl_keeps_resting_orders_alive() {
+    let s = setup();
+    let order_id = s.contract.place_limit_order(&s.trader, &s.market, &true, &(2 * MARGIN), &2, &(PRICE * 9 / 10), &2_000);
+    let ttl = |key: DataKey| s.e.as_contract(&s.contract.address, || s.e.storage().persistent().get_ttl(&key));
+
+    s.e.ledger().with_mut(|l| l.sequence_number += 2 * DAY_IN_LEDGERS);
+    assert!(ttl(DataKey::Order(order_id)) < PERSISTENT_LIFETIME_THRESHOLD);
+
+    s.contract.extend_order_ttl(&order_id);
+    assert_eq!(ttl(DataKey::Order(order_id)), PERSISTENT_BUMP_AMOUNT);
+    assert_eq!(ttl(DataKey::TraderOrders(s.trader.clone())), PERSISTENT_BUMP_AMOUNT);
+    assert_eq!(s.contract.try_extend_order_ttl(&(order_id + 1)), Err(Ok(Error::OrderNotFound)));
+}
+
+#[test]
+fn stop_loss_closes_position_once_crossed() {
+    let s = setup();
+    let oracle = MockPriceOracleClient::new(&s.e, &s.contract.get_config().price_oracle);
+    let stop = Some(PRICE * 95 / 100);
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &stop, &None);
+
+    assert_eq!(s.contract.try_trigger(&position_id), Err(Ok(Error::TriggerNotMet)));
+
+    oracle.set_price(&(PRICE * 94 / 100), &1_000);
+    s.contract.trigger(&position_id);
+    let position = s.contract.get_current_position(&position_id);
+    assert_eq!(position.status, PositionStatus::Closed);
+    assert_eq!(position.close_price, PRICE * 94 / 100);
+}
+
+#[test]
+fn partial_take_profit_disarms_after_firing() {
+    let s = setup();
+    let oracle = MockPriceOracleClient::new(&s.e, &s.contract.get_config().price_oracle);
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &2, &false, &NO_MIN, &None, &None);
+
+    // A short's take-profit must sit below the current price
+    let result = s.contract.try_set_triggers(&s.trader, &position_id, &None, &Some(PRICE * 11 / 10), &MARGIN);
+    assert_eq!(result, Err(Ok(Error::InvalidTrigger)));
+    s.contract.set_triggers(&s.trader, &position_id, &Some(PRICE * 11 / 10), &Some(PRICE * 9 / 10), &MARGIN);
+
+    oracle.set_price(&(PRICE * 9 / 10), &1_000);
+    s.contract.trigger(&position_id);
+    let position = s.contract.get_current_position(&position_id);
+    assert_eq!(position.status, PositionStatus::Open);
+    assert_eq!(position.size, MARGIN);
+    assert_eq!(position.take_profit, None);
+    assert_eq!(position.stop_loss, Some(PRICE * 11 / 10));
+
+    assert_eq!(s.contract.try_trigger(&position_id), Err(Ok(Error::TriggerNotMet)));
+}
+
+#[test]
+fn execution_beyond_acceptable_price_reverts() {
+    let s = setup();
+
+    // Longs buy on open, so the bound is a maximum
+    let result = s.contract.try_open_position(&s.trader, &s.market, &MARGIN, &2, &true, &(PRICE - 1), &None, &None);
+    assert_eq!(result, Err(Ok(Error::SlippageExceeded)));
+    let long_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &2, &true, &PRICE, &None, &None);
+
+    // Shorts sell on open, so the bound is a minimum
+    let result = s.contract.try_open_position(&s.trader, &s.market, &MARGIN, &2, &false, &(PRICE + 1), &None, &None);
+    assert_eq!(result, Err(Ok(Error::SlippageExceeded)));
+    let short_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &2, &false, &PRICE, &None, &None);
+
+    // Closing flips the direction of each bound
+    let result = s.contract.try_close_position(&s.trader, &long_id, &(PRICE + 1));
+    assert_eq!(result, Err(Ok(Error::SlippageExceeded)));
+    let result = s.contract.try_close_position(&s.trader, &short_id, &(PRICE - 1));
+    assert_eq!(result, Err(Ok(Error::SlippageExceeded)));
+
+    s.contract.close_position(&s.trader, &long_id, &PRICE);
+    s.contract.close_position(&s.trader, &short_id, &PRICE);
+}
+
+#[test]
+fn market_order_fills_at_first_price_after_request() {
+    let s = setup();
+    let oracle = MockPriceOracleClient::new(&s.e, &s.contract.get_config().price_oracle);
+    let keeper = Address::generate(&s.e);
+
+    // Requested at 1000 with a 300s resolution, so it fills at the 1200 price
+    let order_id = s.contract.request_market_order(&s.trader, &s.market, &MARGIN, &2, &true, &(PRICE * 11 / 10));
+    let order = s.contract.get_order(&order_id);
+    assert_eq!(order.kind, OrderKind::Market);
+    assert_eq!(order.price_time, 1_200);
+    assert_eq!(s.contract.try_execute_order(&keeper, &order_id), Err(Ok(Error::OrderNotTriggered)));
+
+    oracle.set_price(&(PRICE * 21 / 20), &1_200);
+    oracle.set_price(&(PRICE * 2), &1_450);
+    s.e.ledger().with_mut(|l| l.timestamp = 1_450);
+    assert_eq!(s.contract.try_cancel_order(&s.trader, &order_id), Err(Ok(Error::OrderNotExpired)));
+
+    let position_id = s.contract.execute_order(&keeper, &order_id);
+    assert_eq!(s.contract.get_current_position(&position_id).entry_price, PRICE * 21 / 20);
+}
+
+#[test]
+fn unfilled_market_order_can_be_cancelled_after_timeout() {
+    let s = setup();
+    let oracle = MockPriceOracleClient::new(&s.e, &s.contract.get_config().price_oracle);
+    let token = token::Client::new(&s.e, &s.contract.get_config().collateral_token);
+    let keeper = Address::generate(&s.e);
+    let balance = token.balance(&s.trader);
+
+    // The published price is beyond the acceptable price, so the order cannot fill
+    let order_id = s.contract.request_market_order(&s.trader, &s.market, &MARGIN, &2, &true, &PRICE);
+    oracle.set_price(&(PRICE * 11 / 10), &1_200);
+    s.e.ledger().with_mut(|l| l.timestamp = 1_200);
+    assert_eq!(s.contract.try_execute_order(&keeper, &order_id), Err(Ok(Error::SlippageExceeded)));
+
+    let expiry = s.contract.get_order(&order_id).expiry;
+    s.e.ledger().with_mut(|l| l.timestamp = expiry + 1);
+    assert_eq!(s.contract.try_execute_order(&keeper, &order_id), Err(Ok(Error::OrderExpired)));
+
+    // Only the margin comes back; the keeper fee goes to the insurance fund
+    let keeper_fee = s.contract.get_order(&order_id).keeper_fee;
+    s.contract.cancel_order(&s.trader, &order_id);
+    assert_eq!(token.balance(&s.trader), balance - keeper_fee);
+    assert_eq!(s.contract.get_insurance_balance(), keeper_fee);
+}
+
+#[test]
+fn trader_cannot_execute_own_market_order() {
+    let s = setup();
+    let oracle = MockPriceOracleClient::new(&s.e, &s.contract.get_config().price_oracle);
+    let keeper = Address::generate(&s.e);
+
+    // Once the fill price is known the trader could choose between filling and lapsing
+    let order_id = s.contract.request_market_order(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX);
+    oracle.set_price(&(PRICE * 9 / 10), &1_200);
+    oracle.set_price(&PRICE, &1_300);
+    s.e.ledger().with_mut(|l| l.timestamp = 1_300);
+    assert_eq!(s.contract.try_execute_order(&s.trader, &order_id), Err(Ok(Error::Unauthorized)));
+
+    let position_id = s.contract.execute_order(&keeper, &order_id);
+    assert_eq!(s.contract.get_current_position(&position_id).entry_price, PRICE * 9 / 10);
+}
+
+#[test]
+fn open_interest_caps_bound_market_side_and_trader() {
+    let s = setup();
+    let mut market = s.contract.get_market(&s.market);
+    market.max_open_interest = 6 * MARGIN;
+    market.max_side_open_interest = 4 * MARGIN;
+    market.max_trader_notional = 3 * MARGIN;
+    s.contract.set_market(&s.admin, &market);
+
+    let other = Address::generate(&s.e);
+    token::StellarAssetClient::new(&s.e, &s.contract.get_config().collateral_token).mint(&other, &(10 * MARGIN));
+
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+    let result = s.contract.try_increase_position(&s.trader, &position_id, &MARGIN, &2, &NO_MAX);
+    assert_eq!(result, Err(Ok(Error::TraderNotionalCapExceeded)));
+    assert_eq!(s.contract.get_trader_notional(&s.trader, &s.market), 2 * MARGIN);
+
+    // The long side is now full, and shorts are held to the remaining total
+    s.contract.open_position(&other, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+    let open_interest = s.contract.get_open_interest(&s.market);
+    assert_eq!(open_interest.long_open_interest, 4 * MARGIN);
+    assert_eq!(open_interest.long_capacity, 0);
+    assert_eq!(open_interest.short_capacity, 2 * MARGIN);
+
+    let result = s.contract.try_open_position(&other, &s.market, &(MARGIN / 2), &2, &true, &NO_MAX, &None, &None);
+    assert_eq!(result, Err(Ok(Error::OpenInterestCapExceeded)));
+    let result = s.contract.try_open_position(&other, &s.market, &(3 * MARGIN / 2), &2, &false, &NO_MIN, &None, &None);
+    assert_eq!(result, Err(Ok(Error::OpenInterestCapExceeded)));
+
+    // Reducing frees both the trader's allowance and the side's capacity
+    s.contract.reduce_position(&s.trader, &position_id, &MARGIN, &NO_MIN);
+    assert_eq!(s.contract.get_trader_notional(&s.trader, &s.market), MARGIN);
+    assert_eq!(s.contract.get_open_interest(&s.market).long_capacity, MARGIN);
+
+    s.contract.close_position(&s.trader, &position_id, &NO_MIN);
+    assert_eq!(s.contract.get_trader_notional(&s.trader, &s.market), 0);
+
+    market.max_trader_notional = -1;
+    assert_eq!(s.contract.try_set_market(&s.admin, &market), Err(Ok(Error::InvalidMarketConfig)));
+}
+
+#[test]
+fn skewed_open_interest_pays_funding_to_the_lighter_side() {
+    let s = setup();
+    let token = token::Client::new(&s.e, &s.contract.get_config().collateral_token);
+    let oracle = MockPriceOracleClient::new(&s.e, &s.contract.get_config().price_oracle);
+    let provider = Address::generate(&s.e);
+    token::StellarAssetClient::new(&s.e, &token.address).mint(&provider, &(10 * MARGIN));
+    s.contract.deposit_liquidity(&provider, &(10 * MARGIN));
+
+    let short_trader = Address::generate(&s.e);
+    token::StellarAssetClient::new(&s.e, &token.address).mint(&short_trader, &MARGIN);
+    let long_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &3, &true, &NO_MAX, &None, &None);
+    let short_id = s.contract.open_position(&short_trader, &s.market, &MARGIN, &1, &false, &NO_MIN, &None, &None);
+    let nav = s.contract.get_pool_nav();
+
+    // Longs are 3 of 4 units of open interest, so over a day at 10 bps the index moves
+    // by 10 bps * (3 - 1) / 4 of a unit of size
+    s.e.ledger().with_mut(|l| l.timestamp = 1_000 + 86_400);
+    oracle.set_price(&PRICE, &(1_000 + 86_400));
+    let index_delta = 1_000_000_000 * 10 / 10_000 * 2 / 4;
+    assert_eq!(s.contract.settle_funding(&s.market), index_delta);
+    assert_eq!(s.contract.get_market_state(&s.market).funding_index, index_delta);
+
+    // The long owes funding on 3 * MARGIN and the short is owed it on MARGIN; the net
+    // is what the pool collects
+    let long_funding = 3 * MARGIN * index_delta / 1_000_000_000;
+    let short_funding = MARGIN * index_delta / 1_000_000_000;
+    assert_eq!(s.contract.get_pool_nav(), nav + long_funding - short_funding);
+
+    let long_margin = s.contract.get_current_position(&long_id).margin;
+    let balance = token.balance(&s.trader);
+    assert_eq!(s.contract.close_position(&s.trader, &long_id, &NO_MIN), -long_funding);
+    assert_eq!(token.balance(&s.trader), balance + long_margin - long_funding - 3 * MARGIN * 10 / 10_000);
+
+    let short_margin = s.contract.get_current_position(&short_id).margin;
+    let balance = token.balance(&short_trader);
+    assert_eq!(s.contract.close_position(&short_trader, &short_id, &NO_MAX), short_funding);
+    assert_eq!(token.balance(&short_trader), balance + short_margin + short_funding - MARGIN * 10 / 10_000);
+}
+
+#[test]
+fn accrued_funding_can_make_a_position_liquidatable() {
+    let s = setup();
+    let oracle = MockPriceOracleClient::new(&s.e, &s.contract.get_config().price_oracle);
+    let keeper = Address::generate(&s.e);
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &10, &true, &NO_MAX, &None, &None);
+    let liquidation_price = s.contract.get_liquidation_price(&position_id);
+
+    // With only longs open they pay the full 10 bps a day; sixty days eat 0.6 of the margin
+    let later = 1_000 + 60 * 86_400;
+    s.e.ledger().with_mut(|l| l.timestamp = later);
+    oracle.set_price(&PRICE, &later);
+    assert!(s.contract.get_liquidation_price(&position_id) > liquidation_price);
+    assert!(s.contract.is_liquidatable(&position_id));
+
+    s.contract.liquidate(&keeper, &position_id);
+    let position = s.contract.get_current_position(&position_id);
+    assert_eq!(position.status, PositionStatus::Liquidated);
+    assert_eq!(position.pnl, -10 * MARGIN * 60 / 1_000);
+}
+
+#[test]
+fn liquidation_price_marks_the_maintenance_margin() {
+    let s = setup();
+    let long_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &10, &true, &NO_MAX, &None, &None);
+    let short_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &10, &false, &NO_MIN, &None, &None);
+
+    // 0.99 of margin after the open fee, less 5% of the 10x size, leaves a 4.9% price move
+    assert_eq!(s.contract.get_liquidation_price(&long_id), PRICE - PRICE * 49 / 1_000);
+    assert_eq!(s.contract.get_liquidation_price(&short_id), PRICE + PRICE * 49 / 1_000);
+    assert!(!s.contract.is_liquidatable(&long_id));
+    assert!(!s.contract.is_liquidatable(&short_id));
+}
+
+#[test]
+fn liquidation_above_maintenance_margin_is_rejected() {
+    let s = setup();
+    let oracle = MockPriceOracleClient::new(&s.e, &s.contract.get_config().price_oracle);
+    let keeper = Address::generate(&s.e);
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &10, &true, &NO_MAX, &None, &None);
+
+    oracle.set_price(&(s.contract.get_liquidation_price(&position_id) + 1), &1_000);
+    assert!(!s.contract.is_liquidatable(&position_id));
+    assert_eq!(s.contract.try_liquidate(&keeper, &position_id), Err(Ok(Error::NotLiquidatable)));
+    assert_eq!(s.contract.get_current_position(&position_id).status, PositionStatus::Open);
+}
+
+#[test]
+fn liquidation_splits_the_fee_between_keeper_and_protocol() {
+    let s = setup();
+    let oracle = MockPriceOracleClient::new(&s.e, &s.contract.get_config().price_oracle);
+    let token = token::Client::new(&s.e, &s.contract.get_config().collateral_token);
+    let keeper = Address::generate(&s.e);
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &10, &true, &NO_MAX, &None, &None);
+    let margin = s.contract.get_current_position(&position_id).margin;
+
+    // A 6% drop at 10x leaves 0.39 of margin, below the 0.5 maintenance margin but positive
+    oracle.set_price(&(PRICE * 94 / 100), &1_000);
+    assert!(s.contract.is_liquidatable(&position_id));
+    let balance = token.balance(&s.trader);
+    let protocol_fees = s.contract.get_protocol_fees();
+
+    // 1% liquidation fee on the size, half of it to the keeper
+    let liquidation_fee = 10 * MARGIN / 100;
+    let keeper_reward = liquidation_fee / 2;
+    assert_eq!(s.contract.liquidate(&keeper, &position_id), keeper_reward);
+    assert_eq!(token.balance(&keeper), keeper_reward);
+
+    let config = s.contract.get_config();
+    let protocol_share = keeper_reward
+        - keeper_reward * config.lp_fee_share_bps as i128 / 10_000
+        - keeper_reward * config.insurance_fee_share_bps as i128 / 10_000;
+    assert_eq!(s.contract.get_protocol_fees(), protocol_fees + protocol_share);
+
+    // The trader gets back what is left of the equity after the fee
+    let equity = margin - 6 * MARGIN / 10;
+    assert_eq!(token.balance(&s.trader), balance + equity - liquidation_fee);
+    let position = s.contract.get_current_position(&position_id);
+    assert_eq!(position.status, PositionStatus::Liquidated);
+    assert_eq!(position.close_price, PRICE * 94 / 100);
+    assert_eq!(s.contract.get_market_state(&s.market).long_open_interest, 0);
+    assert_eq!(s.contract.try_liquidate(&keeper, &position_id), Err(Ok(Error::PositionClosed)));
+}
+
+#[test]
+fn close_only_blocks_new_exposure_but_allows_exits() {
+    let s = setup();
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+    s.contract.set_pause_mode(&s.admin, &PauseMode::CloseOnly);
+    assert_eq!(s.contract.get_contract_stats().2, PauseMode::CloseOnly);
+
+    let result = s.contract.try_open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+    assert_eq!(result, Err(Ok(Error::CloseOnly)));
+    let result = s.contract.try_increase_position(&s.trader, &position_id, &MARGIN, &2, &NO_MAX);
+    assert_eq!(result, Err(Ok(Error::CloseOnly)));
+    let result = s.contract.try_request_market_order(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX);
+    assert_eq!(result, Err(Ok(Error::CloseOnly)));
+
+    s.contract.add_margin(&s.trader, &position_id, &MARGIN);
+    s.contract.set_triggers(&s.trader, &position_id, &Some(PRICE / 2), &None, &0);
+    s.contract.close_position(&s.trader, &position_id, &NO_MIN);
+    assert_eq!(s.contract.get_current_position(&position_id).status, PositionStatus::Closed);
+}
+
+#[test]
+fn halt_blocks_all_trading_until_resumed() {
+    let s = setup();
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+    s.contract.pause_contract(&s.admin);
+
+    let result = s.contract.try_open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+    assert_eq!(result, Err(Ok(Error::ContractPaused)));
+    let result = s.contract.try_increase_position(&s.trader, &position_id, &MARGIN, &2, &NO_MAX);
+    assert_eq!(result, Err(Ok(Error::ContractPaused)));
+    assert_eq!(s.contract.try_add_margin(&s.trader, &position_id, &MARGIN), Err(Ok(Error::ContractPaused)));
+    assert_eq!(s.contract.try_close_position(&s.trader, &position_id, &NO_MIN), Err(Ok(Error::ContractPaused)));
+    let result = s.contract.try_set_triggers(&s.trader, &position_id, &Some(PRICE / 2), &None, &0);
+    assert_eq!(result, Err(Ok(Error::ContractPaused)));
+    assert_eq!(s.contract.try_deposit_liquidity(&s.trader, &MARGIN), Err(Ok(Error::ContractPaused)));
+    assert_eq!(s.contract.try_fund_insurance(&s.trader, &MARGIN), Err(Ok(Error::ContractPaused)));
+
+    s.contract.resume_contract(&s.admin);
+    s.contract.fund_insurance(&s.trader, &MARGIN);
+    s.contract.close_position(&s.trader, &position_id, &NO_MIN);
+}
+
+#[test]
+fn paused_market_blocks_only_that_market() {
+    let s = setup();
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+
+    let mut other = s.contract.get_market(&s.market);
+    other.id = Symbol::new(&s.e, "BTC");
+    s.contract.set_market(&s.admin, &other);
+
+    s.contract.pause_market(&s.admin, &s.market);
+    assert!(s.contract.is_market_paused(&s.market));
+    let result = s.contract.try_open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+    assert_eq!(result, Err(Ok(Error::MarketPaused)));
+    let result = s.contract.try_increase_position(&s.trader, &position_id, &MARGIN, &2, &NO_MAX);
+    assert_eq!(result, Err(Ok(Error::MarketPaused)));
+    assert_eq!(s.contract.try_close_position(&s.trader, &position_id, &NO_MIN), Err(Ok(Error::MarketPaused)));
+    let result = s.contract.try_set_triggers(&s.trader, &position_id, &Some(PRICE / 2), &None, &0);
+    assert_eq!(result, Err(Ok(Error::MarketPaused)));
+
+    s.contract.open_position(&s.trader, &other.id, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+
+    s.contract.resume_market(&s.admin, &s.market);
+    s.contract.close_position(&s.trader, &position_id, &NO_MIN);
+}
+
+#[test]
+fn position_lifecycle_publishes_versioned_events() {
+    let s = setup();
+    let oracle = MockPriceOracleClient::new(&s.e, &s.contract.get_config().price_oracle);
+    let keeper = Address::generate(&s.e);
+
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &2, &true, &NO_MAX, &None, &None);
+    let position = s.contract.get_current_position(&position_id);
+    let (topics, data) = last_event(&s, symbol_short!("open"));
+    assert_eq!(topics, (symbol_short!("open"), s.market.clone(), s.trader.clone()).into_val(&s.e));
+    assert_eq!(
+        <(u32, u64, bool, i128, i128, i128)>::try_from_val(&s.e, &data).unwrap(),
+        (1, position_id, true, 2 * MARGIN, position.margin, PRICE)
+    );
+
+    s.contract.close_position(&s.trader, &position_id, &NO_MIN);
+    let payout = position.margin - 2 * MARGIN * 10 / 10_000;
+    let (topics, data) = last_event(&s, symbol_short!("close"));
+    assert_eq!(topics, (symbol_short!("close"), s.market.clone(), s.trader.clone()).into_val(&s.e));
+    assert_eq!(
+        <(u32, u64, i128, i128, i128)>::try_from_val(&s.e, &data).unwrap(),
+        (1, position_id, PRICE, 0, payout)
+    );
+
+    let position_id = s.contract.open_position(&s.trader, &s.market, &MARGIN, &10, &true, &NO_MAX, &None, &None);
+    oracle.set_price(&(PRICE * 94 / 100), &1_000);
+    let keeper_reward = s.contract.liquidate(&keeper, &position_id);
+    let (topics, data) = last_event(&s, symbol_short!("liquidate"));
+    assert_eq!(topics, (symbol_short!("liquidate"), s.market.clone(), s.trader.clone()).into_val(&s.e));
+    assert_eq!(
+        <(u32, u64, Address, i128, i128, i128)>::try_from_val(&s.e, &data).unwrap(),
+        (1, position_id, keeper, PRICE * 94 / 100, -6 * MARGIN / 10, keeper_reward)
+    );
+}
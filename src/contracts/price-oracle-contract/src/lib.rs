@@ -1,6 +1,9 @@
 #![no_std]
 
-use soroban_sdk::{contract, contractimpl, Address, Env, String, Symbol, Vec};
+use soroban_sdk::{contract, contracterror, contractimpl, contracttype, symbol_short, Address, BytesN, Env, String, Symbol, Vec};
+
+#[cfg(test)]
+mod test;
 
 // Reflector Oracle interface
 mod reflector {
@@ -8,10 +11,12 @@ mod reflector {
 
     // Oracle contract interface exported as ReflectorClient
     #[soroban_sdk::contractclient(name = "ReflectorClient")]
+    #[allow(dead_code)]
     pub trait Contract {
         fn base(e: Env) -> Asset;
         fn assets(e: Env) -> Vec<Asset>;
         fn decimals(e: Env) -> u32;
+        fn price(e: Env, asset: Asset, timestamp: u64) -> Option<PriceData>;
         fn lastprice(e: Env, asset: Asset) -> Option<PriceData>;
         fn twap(e: Env, asset: Asset, records: u32) -> Option<i128>;
         fn resolution(e: Env) -> u32;
@@ -34,126 +39,232 @@ mod reflector {
     }
 }
 
+// Instance storage TTL, bumped on every initialize and price read
+const DAY_IN_LEDGERS: u32 = 17280;
+const INSTANCE_BUMP_AMOUNT: u32 = 7 * DAY_IN_LEDGERS;
+const INSTANCE_LIFETIME_THRESHOLD: u32 = INSTANCE_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
+// Current storage schema version, recorded in Config
+const SCHEMA_VERSION: u32 = 1;
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DataKey {
+    Initialized,
+    Config,
+}
+
+// Contract configuration, stored under DataKey::Config
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Config {
+    pub version: u32, // storage schema version
+    pub admin: Address,
+    pub reflector: Address,
+}
+
+// Version of the event payload layout
+const EVENT_VERSION: u32 = 1;
+
+// Possible runtime errors, with stable codes for clients
+#[contracterror]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[repr(u32)]
+pub enum Error {
+    NotInitialized = 1,
+    InvalidPrice = 2,
+    AlreadyInitialized = 3,
+    Unauthorized = 4,
+    InvalidVersion = 5,
+}
+
 #[contract]
 pub struct PriceOracleContract;
 
 #[contractimpl]
 impl PriceOracleContract {
-    // Initialize with Reflector Oracle address
-    pub fn initialize(e: Env, reflector_address: Address) {
-        e.storage().instance().set(&Symbol::new(&e, "reflector"), &reflector_address);
+    // Initialize with admin and Reflector Oracle address
+    pub fn initialize(e: Env, admin: Address, reflector_address: Address) -> Result<(), Error> {
+        if e.storage().instance().has(&DataKey::Initialized) {
+            return Err(Error::AlreadyInitialized);
+        }
+
+        let config = Config {
+            version: SCHEMA_VERSION,
+            admin,
+            reflector: reflector_address.clone(),
+        };
+        e.storage().instance().set(&DataKey::Config, &config);
+        e.storage().instance().set(&DataKey::Initialized, &true);
+        e.storage().instance().extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+        e.events().publish((symbol_short!("init"),), (EVENT_VERSION, reflector_address));
+        Ok(())
+    }
+
+    // Point the contract at a different Reflector Oracle (admin only)
+    pub fn update_config(e: Env, admin: Address, reflector_address: Address) -> Result<(), Error> {
+        let mut config = Self::check_admin(&e, &admin)?;
+        config.reflector = reflector_address.clone();
+        e.storage().instance().set(&DataKey::Config, &config);
+        e.storage().instance().extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+        e.events().publish((symbol_short!("config"), admin), (EVENT_VERSION, reflector_address));
+        Ok(())
+    }
+
+    // Replace the contract code (admin only); storage is kept and brought up to date with migrate
+    pub fn upgrade(e: Env, admin: Address, new_wasm_hash: BytesN<32>) -> Result<(), Error> {
+        Self::check_admin(&e, &admin)?;
+        e.deployer().update_current_contract_wasm(new_wasm_hash.clone());
+        e.events().publish((symbol_short!("upgrade"), admin), (EVENT_VERSION, new_wasm_hash));
+        Ok(())
+    }
+
+    // Storage schema version this code expects
+    pub fn version(_e: Env) -> Result<u32, Error> {
+        Ok(SCHEMA_VERSION)
+    }
+
+    // Bring storage written by an older version up to the current schema (admin only)
+    // Each schema change adds its conversion step here. Builds that predate the upgrade
+    // entrypoint can never reach this code, so their layouts need no step.
+    pub fn migrate(e: Env, admin: Address) -> Result<u32, Error> {
+        let mut config = Self::check_admin(&e, &admin)?;
+        if config.version > SCHEMA_VERSION {
+            return Err(Error::InvalidVersion);
+        }
+        let from_version = config.version;
+
+        config.version = SCHEMA_VERSION;
+        e.storage().instance().set(&DataKey::Config, &config);
+        e.storage().instance().extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+        e.events().publish((symbol_short!("migrate"), admin), (EVENT_VERSION, from_version, SCHEMA_VERSION));
+        Ok(SCHEMA_VERSION)
+    }
+
+    // Get the contract configuration
+    pub fn get_config(e: Env) -> Result<Config, Error> {
+        Self::load_config(&e)
+    }
+
+    // Load the configuration or fail if the contract is not initialized
+    fn load_config(e: &Env) -> Result<Config, Error> {
+        e.storage().instance()
+            .get(&DataKey::Config)
+            .ok_or(Error::NotInitialized)
+    }
+
+    // Verify the caller is the stored admin and has signed the invocation
+    fn check_admin(e: &Env, admin: &Address) -> Result<Config, Error> {
+        let config = Self::load_config(e)?;
+        if config.admin != *admin {
+            return Err(Error::Unauthorized);
+        }
+        admin.require_auth();
+        Ok(config)
+    }
+
+    // Client for the configured Reflector Oracle
+    fn reflector_client(e: &Env) -> Result<reflector::ReflectorClient<'_>, Error> {
+        let config = Self::load_config(e)?;
+        Ok(reflector::ReflectorClient::new(e, &config.reflector))
+    }
+
+    // Get the latest price record for any asset quoted by Reflector
+    pub fn get_price(e: Env, asset: reflector::Asset) -> Result<Option<reflector::PriceData>, Error> {
+        let reflector_client = Self::reflector_client(&e)?;
+        e.storage().instance().extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+        Ok(reflector_client.lastprice(&asset))
+    }
+
+    // Get the price record Reflector published for a specific timestamp
+    pub fn get_price_at(e: Env, asset: reflector::Asset, timestamp: u64) -> Result<Option<reflector::PriceData>, Error> {
+        let reflector_client = Self::reflector_client(&e)?;
+        e.storage().instance().extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+        Ok(reflector_client.price(&asset, &timestamp))
     }
 
     // Get XLM price from Reflector
-    pub fn get_xlm_price(e: Env) -> i128 {
-        let reflector_address: Address = e.storage().instance()
-            .get(&Symbol::new(&e, "reflector"))
-            .expect("Reflector not initialized");
-        
-        let reflector_client = reflector::ReflectorClient::new(&e, &reflector_address);
+    pub fn get_xlm_price(e: Env) -> Result<i128, Error> {
+        let reflector_client = Self::reflector_client(&e)?;
         let xlm_asset = reflector::Asset::Stellar(Address::from_string(&String::from_str(&e, "CAS3J7GYLGXMF6TDJBBYYSE3HQ6BBSMLNUQ34T6TZMYMW2EVH34XOWMA")));
         
         if let Some(price_data) = reflector_client.lastprice(&xlm_asset) {
-            price_data.price
+            Ok(price_data.price)
         } else {
-            0
+            Ok(0)
         }
     }
 
     // Get BTC price from Reflector
-    pub fn get_btc_price(e: Env) -> i128 {
-        let reflector_address: Address = e.storage().instance()
-            .get(&Symbol::new(&e, "reflector"))
-            .expect("Reflector not initialized");
-        
-        let reflector_client = reflector::ReflectorClient::new(&e, &reflector_address);
+    pub fn get_btc_price(e: Env) -> Result<i128, Error> {
+        let reflector_client = Self::reflector_client(&e)?;
         let btc_asset = reflector::Asset::Other(Symbol::new(&e, "BTC"));
         
         if let Some(price_data) = reflector_client.lastprice(&btc_asset) {
-            price_data.price
+            Ok(price_data.price)
         } else {
-            0
+            Ok(0)
         }
     }
 
     // Get ETH price from Reflector
-    pub fn get_eth_price(e: Env) -> i128 {
-        let reflector_address: Address = e.storage().instance()
-            .get(&Symbol::new(&e, "reflector"))
-            .expect("Reflector not initialized");
-        
-        let reflector_client = reflector::ReflectorClient::new(&e, &reflector_address);
+    pub fn get_eth_price(e: Env) -> Result<i128, Error> {
+        let reflector_client = Self::reflector_client(&e)?;
         let eth_asset = reflector::Asset::Other(Symbol::new(&e, "ETH"));
         
         if let Some(price_data) = reflector_client.lastprice(&eth_asset) {
-            price_data.price
+            Ok(price_data.price)
         } else {
-            0
+            Ok(0)
         }
     }
 
     // Get TWAP (Time Weighted Average Price) for XLM
-    pub fn get_xlm_twap(e: Env, records: u32) -> i128 {
-        let reflector_address: Address = e.storage().instance()
-            .get(&Symbol::new(&e, "reflector"))
-            .expect("Reflector not initialized");
-        
-        let reflector_client = reflector::ReflectorClient::new(&e, &reflector_address);
+    pub fn get_xlm_twap(e: Env, records: u32) -> Result<i128, Error> {
+        let reflector_client = Self::reflector_client(&e)?;
         let xlm_asset = reflector::Asset::Stellar(Address::from_string(&String::from_str(&e, "CAS3J7GYLGXMF6TDJBBYYSE3HQ6BBSMLNUQ34T6TZMYMW2EVH34XOWMA")));
         
-        reflector_client.twap(&xlm_asset, &records).unwrap_or(0)
+        Ok(reflector_client.twap(&xlm_asset, &records).unwrap_or(0))
     }
 
     // Check if price is fresh (within last 5 minutes)
-    pub fn is_price_fresh(e: Env) -> bool {
-        let reflector_address: Address = e.storage().instance()
-            .get(&Symbol::new(&e, "reflector"))
-            .expect("Reflector not initialized");
-        
-        let reflector_client = reflector::ReflectorClient::new(&e, &reflector_address);
+    pub fn is_price_fresh(e: Env) -> Result<bool, Error> {
+        let reflector_client = Self::reflector_client(&e)?;
         let current_time = e.ledger().timestamp();
         let last_timestamp = reflector_client.last_timestamp();
         
-        current_time - last_timestamp <= 300 // 5 minutes
+        Ok(current_time.saturating_sub(last_timestamp) <= 300) // 5 minutes
     }
 
     // Get all available assets from Reflector
-    pub fn get_available_assets(e: Env) -> Vec<reflector::Asset> {
-        let reflector_address: Address = e.storage().instance()
-            .get(&Symbol::new(&e, "reflector"))
-            .expect("Reflector not initialized");
-        
-        let reflector_client = reflector::ReflectorClient::new(&e, &reflector_address);
-        reflector_client.assets()
+    pub fn get_available_assets(e: Env) -> Result<Vec<reflector::Asset>, Error> {
+        let reflector_client = Self::reflector_client(&e)?;
+        Ok(reflector_client.assets())
     }
 
     // Get price decimals from Reflector
-    pub fn get_price_decimals(e: Env) -> u32 {
-        let reflector_address: Address = e.storage().instance()
-            .get(&Symbol::new(&e, "reflector"))
-            .expect("Reflector not initialized");
-        
-        let reflector_client = reflector::ReflectorClient::new(&e, &reflector_address);
-        reflector_client.decimals()
+    pub fn get_price_decimals(e: Env) -> Result<u32, Error> {
+        let reflector_client = Self::reflector_client(&e)?;
+        Ok(reflector_client.decimals())
     }
 
     // Get Reflector Oracle info
-    pub fn get_oracle_info(e: Env) -> (u32, u32, u64) {
-        let reflector_address: Address = e.storage().instance()
-            .get(&Symbol::new(&e, "reflector"))
-            .expect("Reflector not initialized");
-        
-        let reflector_client = reflector::ReflectorClient::new(&e, &reflector_address);
+    pub fn get_oracle_info(e: Env) -> Result<(u32, u32, u64), Error> {
+        let reflector_client = Self::reflector_client(&e)?;
         
         let decimals = reflector_client.decimals();
         let resolution = reflector_client.resolution();
         let last_timestamp = reflector_client.last_timestamp();
         
-        (decimals, resolution, last_timestamp)
+        Ok((decimals, resolution, last_timestamp))
     }
 
     // Calculate funding rate based on spot-futures price difference
-    pub fn calculate_funding_rate(e: Env, spot_price: i128, futures_price: i128) -> i128 {
+    pub fn calculate_funding_rate(_e: Env, spot_price: i128, futures_price: i128) -> Result<i128, Error> {
+        if spot_price <= 0 {
+            return Err(Error::InvalidPrice);
+        }
+
         let price_diff = futures_price - spot_price;
         let price_diff_percentage = (price_diff * 10000) / spot_price; // Basis points
         
@@ -161,12 +272,6 @@ impl PriceOracleContract {
         let funding_rate = base_rate + (price_diff_percentage / 100);
         
         // Cap at ±0.1% (±10 basis points)
-        if funding_rate > 10 {
-            10
-        } else if funding_rate < -10 {
-            -10
-        } else {
-            funding_rate
-        }
+        Ok(funding_rate.clamp(-10, 10))
     }
 }
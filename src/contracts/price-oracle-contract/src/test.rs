@@ -0,0 +1,163 @@
+#![cfg(test)]
+
+use soroban_sdk::testutils::{Address as _, Ledger, MockAuth, MockAuthInvoke};
+use soroban_sdk::{contract, contractimpl, symbol_short, Address, BytesN, Env, IntoVal, Symbol};
+
+use crate::reflector::{Asset, PriceData};
+use crate::{Error, PriceOracleContract, PriceOracleContractClient};
+
+// Reflector stand-in serving a single price record for every asset
+#[contract]
+pub struct MockReflector;
+
+#[contractimpl]
+impl MockReflector {
+    pub fn set_price(e: Env, price: i128, timestamp: u64) {
+        let price_data = PriceData { price, timestamp };
+        e.storage().instance().set(&symbol_short!("price"), &price_data);
+        e.storage().instance().set(&timestamp, &price_data);
+    }
+
+    pub fn lastprice(e: Env, _asset: Asset) -> Option<PriceData> {
+        e.storage().instance().get(&symbol_short!("price"))
+    }
+
+    pub fn price(e: Env, _asset: Asset, timestamp: u64) -> Option<PriceData> {
+        e.storage().instance().get(&timestamp)
+    }
+
+    pub fn decimals(_e: Env) -> u32 {
+        14
+    }
+
+    pub fn resolution(_e: Env) -> u32 {
+        300
+    }
+
+    pub fn last_timestamp(e: Env) -> u64 {
+        let last: Option<PriceData> = e.storage().instance().get(&symbol_short!("price"));
+        last.map(|p| p.timestamp).unwrap_or(0)
+    }
+}
+
+struct Setup<'a> {
+    e: Env,
+    contract: PriceOracleContractClient<'a>,
+    admin: Address,
+    reflector: Address,
+}
+
+const PRICE: i128 = 10_000_000;
+
+fn setup<'a>() -> Setup<'a> {
+    let e = Env::default();
+    e.mock_all_auths();
+    e.ledger().with_mut(|l| l.timestamp = 1_000);
+
+    let admin = Address::generate(&e);
+    let reflector = e.register_contract(None, MockReflector);
+    MockReflectorClient::new(&e, &reflector).set_price(&PRICE, &1_000);
+
+    let contract_id = e.register_contract(None, PriceOracleContract);
+    let contract = PriceOracleContractClient::new(&e, &contract_id);
+    contract.initialize(&admin, &reflector);
+
+    Setup { e, contract, admin, reflector }
+}
+
+#[test]
+fn prices_are_read_through_the_configured_reflector() {
+    let s = setup();
+    let asset = Asset::Other(Symbol::new(&s.e, "BTC"));
+
+    assert_eq!(s.contract.get_price(&asset), Some(PriceData { price: PRICE, timestamp: 1_000 }));
+    assert_eq!(s.contract.get_price_at(&asset, &1_000).map(|p| p.price), Some(PRICE));
+    assert_eq!(s.contract.get_price_at(&asset, &700), None);
+    assert_eq!(s.contract.get_btc_price(), PRICE);
+    assert_eq!(s.contract.get_oracle_info(), (14, 300, 1_000));
+    assert!(s.contract.is_price_fresh());
+}
+
+#[test]
+fn initialize_twice_fails() {
+    let s = setup();
+
+    let attacker = Address::generate(&s.e);
+    let result = s.contract.try_initialize(&attacker, &attacker);
+    assert_eq!(result, Err(Ok(Error::AlreadyInitialized)));
+    assert_eq!(s.contract.get_config().admin, s.admin);
+    assert_eq!(s.contract.get_config().reflector, s.reflector);
+}
+
+#[test]
+fn admin_signed_update_config_succeeds() {
+    let s = setup();
+    let reflector = s.e.register_contract(None, MockReflector);
+
+    s.contract
+        .mock_auths(&[MockAuth {
+            address: &s.admin,
+            invoke: &MockAuthInvoke {
+                contract: &s.contract.address,
+                fn_name: "update_config",
+                args: (s.admin.clone(), reflector.clone()).into_val(&s.e),
+                sub_invokes: &[],
+            },
+        }])
+        .update_config(&s.admin, &reflector);
+
+    assert_eq!(s.contract.get_config().reflector, reflector);
+}
+
+#[test]
+fn update_config_signed_by_another_address_fails() {
+    let s = setup();
+    let attacker = Address::generate(&s.e);
+
+    let result = s
+        .contract
+        .mock_auths(&[MockAuth {
+            address: &attacker,
+            invoke: &MockAuthInvoke {
+                contract: &s.contract.address,
+                fn_name: "update_config",
+                args: (s.admin.clone(), attacker.clone()).into_val(&s.e),
+                sub_invokes: &[],
+            },
+        }])
+        .try_update_config(&s.admin, &attacker);
+    assert!(result.is_err());
+    assert_eq!(s.contract.get_config().reflector, s.reflector);
+}
+
+#[test]
+fn privileged_calls_by_non_admin_are_unauthorized() {
+    let s = setup();
+    let other = Address::generate(&s.e);
+
+    assert_eq!(s.contract.try_update_config(&other, &other), Err(Ok(Error::Unauthorized)));
+    assert_eq!(
+        s.contract.try_upgrade(&other, &BytesN::from_array(&s.e, &[0; 32])),
+        Err(Ok(Error::Unauthorized))
+    );
+    assert_eq!(s.contract.try_migrate(&other), Err(Ok(Error::Unauthorized)));
+}
+
+#[test]
+fn privileged_calls_without_admin_auth_fail() {
+    let s = setup();
+    s.e.mock_auths(&[]);
+
+    assert!(s.contract.try_update_config(&s.admin, &s.admin).is_err());
+    assert!(s.contract.try_upgrade(&s.admin, &BytesN::from_array(&s.e, &[0; 32])).is_err());
+    assert!(s.contract.try_migrate(&s.admin).is_err());
+}
+
+#[test]
+fn migrate_keeps_current_config() {
+    let s = setup();
+
+    assert_eq!(s.contract.migrate(&s.admin), s.contract.version());
+    assert_eq!(s.contract.get_config().version, s.contract.version());
+    assert_eq!(s.contract.get_config().reflector, s.reflector);
+}
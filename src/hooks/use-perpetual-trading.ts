@@ -23,9 +23,11 @@ export const usePerpetualTrading = () => {
   // Abrir posición
   const openPosition = useCallback(async (
     traderAddress: string,
+    market: string,
     marginAmount: number, // en XLM
     leverage: number,
-    isLong: boolean
+    isLong: boolean,
+    acceptablePrice: bigint
   ): Promise<number> => {
     try {
       setState(prev => ({ ...prev, isLoading: true, error: null }));
@@ -33,13 +35,15 @@ export const usePerpetualTrading = () => {
       const marginInStroops = Math.floor(marginAmount * 10_000_000);
       const positionId = await perpetualTradingClient.openPosition(
         traderAddress,
+        market,
         marginInStroops,
         leverage,
-        isLong
+        isLong,
+        acceptablePrice
       );
       
       // Actualizar estado después de abrir posición
-      await refreshData();
+      await Promise.all([getCurrentPosition(positionId), refreshData()]);
       
       setState(prev => ({ ...prev, isLoading: false }));
       return positionId;
@@ -57,15 +61,16 @@ export const usePerpetualTrading = () => {
   // Cerrar posición
   const closePosition = useCallback(async (
     traderAddress: string,
-    positionId: number
+    positionId: number,
+    acceptablePrice: bigint
   ): Promise<number> => {
     try {
       setState(prev => ({ ...prev, isLoading: true, error: null }));
       
-      const pnl = await perpetualTradingClient.closePosition(traderAddress, positionId);
+      const pnl = await perpetualTradingClient.closePosition(traderAddress, positionId, acceptablePrice);
       
       // Actualizar estado después de cerrar posición
-      await refreshData();
+      await Promise.all([getCurrentPosition(positionId), refreshData()]);
       
       setState(prev => ({ ...prev, isLoading: false }));
       return pnl;
@@ -80,12 +85,12 @@ export const usePerpetualTrading = () => {
     }
   }, []);
 
-  // Obtener posición actual
-  const getCurrentPosition = useCallback(async (): Promise<Position | null> => {
+  // Obtener una posición por ID
+  const getCurrentPosition = useCallback(async (positionId: number): Promise<Position | null> => {
     try {
       setState(prev => ({ ...prev, isLoading: true, error: null }));
       
-      const position = await perpetualTradingClient.getCurrentPosition();
+      const position = await perpetualTradingClient.getCurrentPosition(positionId);
       
       setState(prev => ({ ...prev, currentPosition: position, isLoading: false }));
       return position;
@@ -234,14 +239,13 @@ export const usePerpetualTrading = () => {
   const refreshData = useCallback(async () => {
     try {
       await Promise.all([
-        getCurrentPosition(),
         getContractStats(),
         getContractBalance(),
       ]);
     } catch (error) {
       console.error('❌ Error refrescando datos:', error);
     }
-  }, [getCurrentPosition, getContractStats, getContractBalance]);
+  }, [getContractStats, getContractBalance]);
 
   // Limpiar error
   const clearError = useCallback(() => {
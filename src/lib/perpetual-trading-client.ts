@@ -6,21 +6,40 @@ const PERPETUAL_CONTRACT_ID = 'CAPQ332GONME6T2EKRWQG4PEASJL6362KSFU33W7U4EEMOZC5
 const RPC_URL = 'https://soroban-testnet.stellar.org:443';
 const NETWORK_PASSPHRASE = 'Test SDF Network ; September 2015';
 
+// Límites de precio aceptable que no restringen la ejecución
+export const NO_MAX_PRICE = 2n ** 127n - 1n;
+export const NO_MIN_PRICE = 0n;
+
+export type PositionStatus = 'Open' | 'Closed' | 'Liquidated';
+export type PauseMode = 'Active' | 'CloseOnly' | 'Halted';
+
+// Espejo del struct Position del contrato
 export interface Position {
+  id: number;
   trader: string;
+  market: string;
   margin: number;
   leverage: number;
   size: number;
-  side: number; // 1 = long, -1 = short
+  isLong: boolean;
   entryPrice: number;
-  timestamp: number;
-  isActive: number;
+  fundingIndex: number;
+  openedAt: number;
+  status: PositionStatus;
+  closePrice: number;
+  closedAt: number;
+  pnl: number;
+  realizedPnl: number;
+  feesPaid: number;
+  stopLoss: number | null;
+  takeProfit: number | null;
+  triggerSize: number;
 }
 
 export interface ContractStats {
   totalBalance: number;
   nextPositionId: number;
-  isActive: boolean;
+  pauseMode: PauseMode;
 }
 
 export class PerpetualTradingClient {
@@ -35,7 +54,7 @@ export class PerpetualTradingClient {
   }
 
   // Inicializar el contrato
-  async initialize(adminAddress: string, priceOracleAddress: string): Promise<boolean> {
+  async initialize(adminAddress: string, priceOracleAddress: string, collateralTokenAddress: string): Promise<boolean> {
     try {
       console.log('🔧 Inicializando contrato de trading...');
       
@@ -46,7 +65,8 @@ export class PerpetualTradingClient {
         server,
         'initialize',
         Address.fromString(adminAddress).toScVal(),
-        Address.fromString(priceOracleAddress).toScVal()
+        Address.fromString(priceOracleAddress).toScVal(),
+        Address.fromString(collateralTokenAddress).toScVal()
       );
 
       console.log('✅ Contrato inicializado:', result);
@@ -58,14 +78,19 @@ export class PerpetualTradingClient {
   }
 
   // Abrir posición con transferencia directa de XLM
+  // Revierte si el precio del oráculo supera acceptablePrice (long) o queda por debajo (short)
   async openPosition(
     traderAddress: string,
+    market: string,
     marginAmount: number, // en stroops (1 XLM = 10,000,000 stroops)
     leverage: number,
-    isLong: boolean
+    isLong: boolean,
+    acceptablePrice: bigint,
+    stopLoss: number | null = null,
+    takeProfit: number | null = null
   ): Promise<number> {
     try {
-      console.log(`🚀 Abriendo posición: ${marginAmount / 10_000_000} XLM, ${leverage}x, ${isLong ? 'LONG' : 'SHORT'}`);
+      console.log(`🚀 Abriendo posición en ${market}: ${marginAmount / 10_000_000} XLM, ${leverage}x, ${isLong ? 'LONG' : 'SHORT'}`);
       
       const server = new SorobanRpc.Server(this.rpcUrl);
       const contract = new Contract(this.contractId);
@@ -74,9 +99,13 @@ export class PerpetualTradingClient {
         server,
         'open_position',
         Address.fromString(traderAddress).toScVal(),
+        xdr.ScVal.scvSymbol(market),
         xdr.ScVal.scvI128(xdr.Int128Parts.fromString(marginAmount.toString())),
         xdr.ScVal.scvU32(leverage),
-        xdr.ScVal.scvBool(isLong)
+        xdr.ScVal.scvBool(isLong),
+        xdr.ScVal.scvI128(xdr.Int128Parts.fromString(acceptablePrice.toString())),
+        this.optionI128(stopLoss),
+        this.optionI128(takeProfit)
       );
 
       const positionId = this.parseU64(result);
@@ -89,7 +118,8 @@ export class PerpetualTradingClient {
   }
 
   // Cerrar posición y recibir XLM + PnL
-  async closePosition(traderAddress: string, positionId: number): Promise<number> {
+  // Revierte si el precio del oráculo queda por debajo de acceptablePrice (long) o lo supera (short)
+  async closePosition(traderAddress: string, positionId: number, acceptablePrice: bigint): Promise<number> {
     try {
       console.log(`🔒 Cerrando posición ${positionId}...`);
       
@@ -100,7 +130,8 @@ export class PerpetualTradingClient {
         server,
         'close_position',
         Address.fromString(traderAddress).toScVal(),
-        xdr.ScVal.scvU64(xdr.Uint64.fromString(positionId.toString()))
+        xdr.ScVal.scvU64(xdr.Uint64.fromString(positionId.toString())),
+        xdr.ScVal.scvI128(xdr.Int128Parts.fromString(acceptablePrice.toString()))
       );
 
       const pnl = this.parseI128(result);
@@ -112,27 +143,42 @@ export class PerpetualTradingClient {
     }
   }
 
-  // Obtener posición actual
-  async getCurrentPosition(): Promise<Position> {
+  // Obtener una posición por ID
+  async getCurrentPosition(positionId: number): Promise<Position> {
     try {
-      console.log('📊 Obteniendo posición actual...');
+      console.log(`📊 Obteniendo posición ${positionId}...`);
       
       const server = new SorobanRpc.Server(this.rpcUrl);
       const contract = new Contract(this.contractId);
       
-      const result = await contract.call(server, 'get_current_position');
+      const result = await contract.call(
+        server,
+        'get_current_position',
+        xdr.ScVal.scvU64(xdr.Uint64.fromString(positionId.toString()))
+      );
       
-      // Parsear resultado como tupla
-      const values = this.parseTuple(result);
+      // Parsear resultado como struct (mapa con claves en snake_case)
+      const fields = this.parseStruct(result);
       const position: Position = {
-        trader: this.parseAddress(values[0]),
-        margin: this.parseI128(values[1]),
-        leverage: this.parseI128(values[2]),
-        size: this.parseI128(values[3]),
-        side: this.parseI128(values[4]),
-        entryPrice: this.parseI128(values[5]),
-        timestamp: this.parseI128(values[6]),
-        isActive: this.parseI128(values[7])
+        id: this.parseU64(fields.id),
+        trader: this.parseAddress(fields.trader),
+        market: this.parseSymbol(fields.market),
+        margin: this.parseI128(fields.margin),
+        leverage: fields.leverage.u32(),
+        size: this.parseI128(fields.size),
+        isLong: this.parseBool(fields.is_long),
+        entryPrice: this.parseI128(fields.entry_price),
+        fundingIndex: this.parseI128(fields.funding_index),
+        openedAt: this.parseU64(fields.opened_at),
+        status: this.parseEnum(fields.status) as PositionStatus,
+        closePrice: this.parseI128(fields.close_price),
+        closedAt: this.parseU64(fields.closed_at),
+        pnl: this.parseI128(fields.pnl),
+        realizedPnl: this.parseI128(fields.realized_pnl),
+        feesPaid: this.parseI128(fields.fees_paid),
+        stopLoss: this.parseOptionI128(fields.stop_loss),
+        takeProfit: this.parseOptionI128(fields.take_profit),
+        triggerSize: this.parseI128(fields.trigger_size)
       };
 
       console.log('✅ Posición actual obtenida:', position);
@@ -180,7 +226,7 @@ export class PerpetualTradingClient {
       const stats: ContractStats = {
         totalBalance: this.parseI128(values[0]),
         nextPositionId: this.parseU64(values[1]),
-        isActive: this.parseBool(values[2])
+        pauseMode: this.parseEnum(values[2]) as PauseMode
       };
 
       console.log('✅ Estadísticas obtenidas:', stats);
@@ -294,6 +340,35 @@ export class PerpetualTradingClient {
   private parseVecU64(scVal: xdr.ScVal): number[] {
     return scVal.vec().map(v => this.parseU64(v));
   }
+
+  private parseSymbol(scVal: xdr.ScVal): string {
+    return scVal.sym().toString();
+  }
+
+  // Los structs del contrato llegan como mapas indexados por nombre de campo
+  private parseStruct(scVal: xdr.ScVal): Record<string, xdr.ScVal> {
+    const fields: Record<string, xdr.ScVal> = {};
+    for (const entry of scVal.map()) {
+      fields[this.parseSymbol(entry.key())] = entry.val();
+    }
+    return fields;
+  }
+
+  // Las variantes de enum sin datos llegan como un vector con su nombre
+  private parseEnum(scVal: xdr.ScVal): string {
+    return this.parseSymbol(scVal.vec()[0]);
+  }
+
+  // Option<i128>: None llega como void
+  private parseOptionI128(scVal: xdr.ScVal): number | null {
+    return scVal.switch() === xdr.ScValType.scvVoid() ? null : this.parseI128(scVal);
+  }
+
+  private optionI128(value: number | null): xdr.ScVal {
+    return value === null
+      ? xdr.ScVal.scvVoid()
+      : xdr.ScVal.scvI128(xdr.Int128Parts.fromString(value.toString()));
+  }
 }
 
 // Instancia singleton